@@ -1,5 +1,6 @@
-import { getArenaList, join } from '@/actions/socket'
+import { desyncDetected, getArenaList, join, joinByCode } from '@/actions/socket'
 import createSimpleAction from '@/utils/createSimpleAction'
+import inviteCode, { clearInviteCode } from '@/utils/inviteCode'
 import socketStates from '@/utils/socketStates'
 
 export * from '@/actions/socket'
@@ -19,8 +20,11 @@ export const setPlayerName = createSimpleAction(SET_PLAYER_NAME, 'name')
 export const setPlayerColor = createSimpleAction(SET_PLAYER_COLOR, 'color')
 
 export const SET_SOCKET_STATE = 'SET_SOCKET_STATE'
+export const SHOW_SERVER_NOTICE = 'SHOW_SERVER_NOTICE'
 export const RECEIVE_SOCKET_MESSAGE = 'RECEIVE_SOCKET_MESSAGE'
 
+export const showServerNotice = createSimpleAction(SHOW_SERVER_NOTICE, 'text')
+
 export function setSocketState(socketState) {
   return (dispatch) => {
     dispatch({ type: SET_SOCKET_STATE, socketState })
@@ -36,6 +40,11 @@ export function receiveSocketMessage(messageType, messageData) {
       ArenaJoined: receiveArenaJoined,
       ArenaState: receiveArenaState,
       ArenaStatePatch: receiveArenaStatePatch,
+      InputAck: receiveInputAck,
+      InputRejected: receiveInputRejected,
+      LightcycleSnapshot: receiveLightcycleSnapshot,
+      ServerShutdown: receiveServerShutdown,
+      Announcement: receiveAnnouncement,
     }
 
     if (typeHandlers[messageType]) return dispatch(typeHandlers[messageType](messageData))
@@ -47,13 +56,22 @@ export const RECEIVE_ARENA_LIST = 'RECEIVE_ARENA_LIST'
 export const RECEIVE_ARENA_JOINED = 'RECEIVE_ARENA_JOINED'
 export const RECEIVE_ARENA_STATE = 'RECEIVE_ARENA_STATE'
 export const RECEIVE_ARENA_STATE_PATCH = 'RECEIVE_ARENA_STATE_PATCH'
+export const RECEIVE_INPUT_ACK = 'RECEIVE_INPUT_ACK'
+export const RECEIVE_INPUT_REJECTED = 'RECEIVE_INPUT_REJECTED'
+export const RECEIVE_LIGHTCYCLE_SNAPSHOT = 'RECEIVE_LIGHTCYCLE_SNAPSHOT'
+export const RECEIVE_SERVER_SHUTDOWN = 'RECEIVE_SERVER_SHUTDOWN'
+export const RECEIVE_ANNOUNCEMENT = 'RECEIVE_ANNOUNCEMENT'
 
 export function receiveArenaList(arenaList) {
   return (dispatch, getState) => {
     const prevArenaList = getState().arenaList
     dispatch({ type: RECEIVE_ARENA_LIST, arenaList })
     if (prevArenaList === null) {
-      if (arenaList.length < 1) dispatch(join())
+      const arenaCode = inviteCode()
+      clearInviteCode()
+
+      if (arenaCode !== null) dispatch(joinByCode(arenaCode))
+      else if (arenaList.length < 1) dispatch(join())
       else dispatch(setStage('ArenaSelect'))
     }
   }
@@ -65,4 +83,15 @@ export function receiveArenaJoined([arenaId, playerId]) {
   }
 }
 export const receiveArenaState = createSimpleAction(RECEIVE_ARENA_STATE, 'state')
-export const receiveArenaStatePatch = createSimpleAction(RECEIVE_ARENA_STATE_PATCH, 'statePatch')
+export function receiveArenaStatePatch(statePatch) {
+  return (dispatch, getState) => {
+    const wasDesynced = getState().arena?.desynced
+    dispatch({ type: RECEIVE_ARENA_STATE_PATCH, statePatch })
+    if (!wasDesynced && getState().arena?.desynced) dispatch(desyncDetected())
+  }
+}
+export const receiveInputAck = createSimpleAction(RECEIVE_INPUT_ACK, 'inputAck')
+export const receiveInputRejected = createSimpleAction(RECEIVE_INPUT_REJECTED, 'inputRejected')
+export const receiveLightcycleSnapshot = createSimpleAction(RECEIVE_LIGHTCYCLE_SNAPSHOT, 'snapshot')
+export const receiveServerShutdown = createSimpleAction(RECEIVE_SERVER_SHUTDOWN, 'serverShutdown')
+export const receiveAnnouncement = createSimpleAction(RECEIVE_ANNOUNCEMENT, 'announcement')
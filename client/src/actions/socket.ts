@@ -1,4 +1,4 @@
-import { receiveSocketMessage, setSocketState } from '@/actions'
+import { receiveSocketMessage, setSocketState, showServerNotice } from '@/actions'
 import defaultPlayer from '@/utils/defaultPlayer'
 import socketStates from '@/utils/socketStates'
 
@@ -8,8 +8,10 @@ export const CONNECT = 'CONNECT'
 export const SEND = 'SEND'
 export const GET_ARENA_LIST = 'GET_ARENA_LIST'
 export const JOIN = 'JOIN'
+export const JOIN_BY_CODE = 'JOIN_BY_CODE'
 export const START = 'START'
 export const TURN = 'TURN'
+export const DESYNC_DETECTED = 'DESYNC_DETECTED'
 
 export function connect() {
   return (dispatch) => {
@@ -38,7 +40,9 @@ export function connect() {
       console.error('socket error', event)
       dispatch(setSocketState(socketStates.CLOSING))
     })
-    socket.addEventListener('close', () => {
+    socket.addEventListener('close', (event) => {
+      // the server explains why it closed the connection (kicked, banned, arena closed...)
+      if (event.reason) dispatch(showServerNotice(event.reason))
       dispatch(setSocketState(socketStates.CLOSED))
       socket = null
     })
@@ -68,6 +72,14 @@ export function join(arenaId = null) {
   }
 }
 
+export function joinByCode(arenaCode) {
+  return (dispatch, getState) => {
+    const { player } = getState()
+    dispatch({ type: JOIN_BY_CODE, player, arenaCode })
+    dispatch(send({ JoinByCode: { player: defaultPlayer(player), arena_code: arenaCode } }))
+  }
+}
+
 export function start() {
   return (dispatch) => {
     dispatch({ type: START })
@@ -75,9 +87,21 @@ export function start() {
   }
 }
 
+let inputSequence = 0
+
 export function turn(direction) {
+  return (dispatch, getState) => {
+    const { arena } = getState()
+    inputSequence += 1
+
+    dispatch({ type: TURN, direction, sequence: inputSequence })
+    dispatch(send({ Turn: { direction, sequence: inputSequence, tick: arena ? arena.tick : 0 } }))
+  }
+}
+
+export function desyncDetected() {
   return (dispatch) => {
-    dispatch({ type: TURN, direction })
-    dispatch(send({ Turn: direction }))
+    dispatch({ type: DESYNC_DETECTED })
+    dispatch(send({ DesyncDetected: null }))
   }
 }
@@ -31,6 +31,7 @@ export default function Arena() {
 
   const Arena = useClassName(styles.arena)
   const Background = useClassName([styles.background, arena.started !== null && styles.backgroundStarted])
+  const ArenaCode = useClassName(styles.arenaCode)
   const WinnerText = useClassName(styles.winnerText)
   const StartButton = useClassName(styles.startButton, MenuButton)
 
@@ -46,6 +47,7 @@ export default function Arena() {
           key={id}
           name={arena.players[id] ? arena.players[id].name : 'DISCONNECTED'}
           color={arena.players[id] ? arena.players[id].color : 'white'}
+          ping={arena.pings ? arena.pings[id] : undefined}
           position={position}
           direction={direction}
           speed={speed}
@@ -53,6 +55,8 @@ export default function Arena() {
         />
       ))}
 
+      {arena.code && <ArenaCode>{`/a/${arena.code}`}</ArenaCode>}
+
       {winner && (
         <WinnerText style={{ color: colorToHexString(winner.color), borderColor: colorToHexString(winner.color) }}>
           {`${winner.name} wins!`.toUpperCase()}
@@ -43,7 +43,7 @@ export default function ArenaSelect() {
             <ArenaSpace />
             <ArenaPlayers>
               {Object.entries(arena.players).map(([playerId, player]) => (
-                <ArenaPlayer key={playerId} src={lightcycleImages[player.color]} />
+                <ArenaPlayer key={playerId} src={lightcycleImages[player.color] || lightcycleImages.white} />
               ))}
               {[...Array(arena.max_players - Object.keys(arena.players).length)].map((_, index) => (
                 <ArenaPlayer key={index} src={lightcycleImages['dark']} />
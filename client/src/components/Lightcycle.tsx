@@ -8,7 +8,7 @@ import lightcycleImages from '@/utils/lightcycleImages'
 
 import styles from './Lightcycle.module.css'
 
-export default function Lightcycle({ name, color, position = [0, 0], direction, speed, dead }) {
+export default function Lightcycle({ name, color, ping, position = [0, 0], direction, speed, dead }) {
   const {
     arena: { width, height },
   } = useStore()
@@ -31,14 +31,17 @@ export default function Lightcycle({ name, color, position = [0, 0], direction,
 
   return (
     <>
-      <NameTag style={{ left, bottom: tagBottom, color: colorToHexString(color) }}>{name}</NameTag>
-      <Lightcycle src={lightcycleImages[color]} style={{ left, bottom, transform }} />
+      <NameTag style={{ left, bottom: tagBottom, color: colorToHexString(color) }}>
+        {ping === undefined ? name : `${name} ${ping}MS`}
+      </NameTag>
+      <Lightcycle src={lightcycleImages[color] || lightcycleImages.white} style={{ left, bottom, transform }} />
     </>
   )
 }
 Lightcycle.propTypes = {
   name: PropTypes.string.isRequired,
   color: PropTypes.string.isRequired,
+  ping: PropTypes.number,
   position: PropTypes.arrayOf(PropTypes.number).isRequired,
   direction: PropTypes.string.isRequired,
   speed: PropTypes.number.isRequired,
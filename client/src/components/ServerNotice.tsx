@@ -0,0 +1,13 @@
+import useClassName from '@/hooks/useClassName'
+import useStore from '@/hooks/useStore'
+
+import styles from './ServerNotice.module.css'
+
+export default function ServerNotice() {
+  const { serverNotice } = useStore()
+
+  const ServerNotice = useClassName(styles.serverNotice)
+
+  if (serverNotice === null) return null
+  return <ServerNotice>{serverNotice.text.toUpperCase()}</ServerNotice>
+}
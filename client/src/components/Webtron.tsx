@@ -2,6 +2,7 @@ import Arena from '@/components/Arena'
 import ArenaSelect from '@/components/ArenaSelect'
 import Connect from '@/components/Connect'
 import MainMenu from '@/components/MainMenu'
+import ServerNotice from '@/components/ServerNotice'
 import useStore from '@/hooks/useStore'
 
 const stages = { MainMenu, Connect, ArenaSelect, Arena }
@@ -16,5 +17,10 @@ export default function Webtron() {
     return null
   }
 
-  return <Stage />
+  return (
+    <>
+      <ServerNotice />
+      <Stage />
+    </>
+  )
 }
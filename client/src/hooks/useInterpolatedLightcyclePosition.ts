@@ -3,9 +3,13 @@ import { useCallback, useRef, useState } from 'react'
 import useRequestAnimationFrame from '@/hooks/useRequestAnimationFrame'
 import useStore from '@/hooks/useStore'
 
+// don't run lightcycles further ahead than this many missed patches
+const MAX_EXTRAPOLATED_PATCHES = 2
+const DEFAULT_SEND_RATE = 20
+
 export default function useInterpolatedLightcyclePosition(position, direction, speed, dead) {
   const {
-    arena: { started },
+    arena: { started, sendRate = DEFAULT_SEND_RATE },
   } = useStore()
 
   const basePosition = useRef(position)
@@ -28,13 +32,13 @@ export default function useInterpolatedLightcyclePosition(position, direction, s
       basePositionSetAt.current = Date.now()
     }
 
-    const deltaTime = (Date.now() - basePositionSetAt.current) / 1000
+    const deltaTime = Math.min((Date.now() - basePositionSetAt.current) / 1000, MAX_EXTRAPOLATED_PATCHES / sendRate)
 
     setInterpolatedPosition([
       basePosition.current[0] + (direction === 'left' ? -1 : direction === 'right' ? 1 : 0) * speed * deltaTime,
       basePosition.current[1] + (direction === 'down' ? -1 : direction === 'up' ? 1 : 0) * speed * deltaTime,
     ])
-  }, [started, direction, speed, dead])
+  }, [started, sendRate, direction, speed, dead])
 
   useRequestAnimationFrame(interpolate)
 
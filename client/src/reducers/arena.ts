@@ -1,6 +1,14 @@
 import dayjs from 'dayjs'
 
-import { RECEIVE_ARENA_JOINED, RECEIVE_ARENA_STATE, RECEIVE_ARENA_STATE_PATCH } from '@/actions'
+import {
+  RECEIVE_ARENA_JOINED,
+  RECEIVE_ARENA_STATE,
+  RECEIVE_ARENA_STATE_PATCH,
+  RECEIVE_INPUT_ACK,
+  RECEIVE_INPUT_REJECTED,
+  RECEIVE_LIGHTCYCLE_SNAPSHOT,
+} from '@/actions'
+import arenaChecksum from '@/utils/arenaChecksum'
 import createReducer from '@/utils/createReducer'
 
 const initialState = null
@@ -8,6 +16,7 @@ const initialState = null
 export default createReducer(initialState, {
   [RECEIVE_ARENA_JOINED]: (_, { arenaId }) => ({
     id: arenaId,
+    code: '',
     name: '',
     width: 0,
     height: 0,
@@ -15,15 +24,34 @@ export default createReducer(initialState, {
 
     started: null,
     winner: null,
+    tick: 0,
 
     players: {},
     lightcycles: {},
     lightribbons: {},
+    pings: {},
   }),
   [RECEIVE_ARENA_STATE]: (_, { state }) => ({ ...state }),
-  [RECEIVE_ARENA_STATE_PATCH]: (arena, { statePatch = [] }) => statePatch.reduce(updateArena, arena),
+  [RECEIVE_ARENA_STATE_PATCH]: (arena, { statePatch }) =>
+    withServerTiming((statePatch?.updates || []).reduce(updateArena, arena), statePatch),
+  [RECEIVE_LIGHTCYCLE_SNAPSHOT]: (arena, { snapshot }) =>
+    arena && {
+      ...withServerTiming(arena, snapshot),
+      lightcycles: { ...arena.lightcycles, ...snapshot.lightcycles },
+    },
+  [RECEIVE_INPUT_ACK]: (arena, { inputAck }) => arena && { ...arena, lastInputAck: inputAck },
+  [RECEIVE_INPUT_REJECTED]: (arena, { inputRejected }) =>
+    arena && { ...arena, lastInputRejected: inputRejected },
 })
 
+// patches and snapshots are stamped with the server tick and time, for interpolating between them
+function withServerTiming(
+  arena,
+  { tick, timestamp, tick_rate: tickRate = arena.tickRate, send_rate: sendRate = arena.sendRate },
+) {
+  return { ...arena, tick, tickRate, sendRate, serverTime: dayjs(timestamp), receivedAt: dayjs() }
+}
+
 function updateArena(arena, update) {
   const updateType = typeof update === 'object' ? Object.keys(update).pop() : update
   const updateData = typeof update === 'object' ? update[updateType] : null
@@ -54,6 +82,7 @@ function updateArena(arena, update) {
     Start: (startAt) => ({
       ...arena,
       started: dayjs(startAt),
+      tick: 0,
     }),
     End: () => ({
       ...arena,
@@ -63,6 +92,18 @@ function updateArena(arena, update) {
       ...arena,
       winner,
     }),
+    Checksum: (checksum) => ({
+      ...arena,
+      desynced: arenaChecksum(arena) !== checksum,
+    }),
+
+    UpdatePlayerPing: ([playerId, ping]) => ({
+      ...arena,
+      pings: {
+        ...arena.pings,
+        [playerId]: ping,
+      },
+    }),
 
     UpdateLightcyclePosition: ([playerId, position]) => ({
       ...arena,
@@ -119,6 +160,7 @@ function updateArena(arena, update) {
     RemovePlayer: (playerId) => ({
       ...arena,
       players: Object.fromEntries(Object.entries(arena.players).filter(([id]) => id !== playerId)),
+      pings: Object.fromEntries(Object.entries(arena.pings || {}).filter(([id]) => id !== playerId)),
     }),
     RemoveLightcycle: (playerId) => ({
       ...arena,
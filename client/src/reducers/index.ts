@@ -5,6 +5,7 @@ import arenaList from './arenaList'
 import config from './config'
 import player from './player'
 import preloadedImages from './preloadedImages'
+import serverNotice from './serverNotice'
 import socketState from './socketState'
 import stage from './stage'
 
@@ -14,6 +15,7 @@ export default combineReducers({
   config,
   player,
   preloadedImages,
+  serverNotice,
   socketState,
   stage,
 })
@@ -0,0 +1,18 @@
+import dayjs from 'dayjs'
+
+import { RECEIVE_ANNOUNCEMENT, RECEIVE_SERVER_SHUTDOWN, SET_SOCKET_STATE, SHOW_SERVER_NOTICE } from '@/actions'
+import createReducer from '@/utils/createReducer'
+import socketStates from '@/utils/socketStates'
+
+const initialState = null
+
+export default createReducer(initialState, {
+  [RECEIVE_SERVER_SHUTDOWN]: (_, { serverShutdown: { in_seconds: inSeconds, reason } }) => ({
+    text: reason,
+    until: dayjs().add(inSeconds, 'second'),
+  }),
+  [RECEIVE_ANNOUNCEMENT]: (_, { announcement: { text } }) => ({ text, until: null }),
+  [SHOW_SERVER_NOTICE]: (_, { text }) => ({ text, until: null }),
+  // notices are about the server we were connected to, so a new connection clears them
+  [SET_SOCKET_STATE]: (notice, { socketState }) => (socketState === socketStates.OPEN ? null : notice),
+})
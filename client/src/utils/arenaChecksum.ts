@@ -0,0 +1,31 @@
+// keep in step with Arena::checksum in src/server/arena/checksum.rs
+export default function arenaChecksum(arena) {
+  let canonical = ''
+
+  for (const [id, player] of sortedById(arena.players)) {
+    canonical += `p${id}:${player.name}:${player.color};`
+  }
+  for (const [id, { position, direction, dead }] of sortedById(arena.lightcycles)) {
+    canonical += `c${id}:${quantize(position[0])},${quantize(position[1])}:${direction}:${dead ? 1 : 0};`
+  }
+  for (const [id, { points }] of sortedById(arena.lightribbons)) {
+    canonical += `r${id}:${points.map((point) => `${quantize(point[0])},${quantize(point[1])}`).join(' ')};`
+  }
+  if (arena.winner) canonical += `w${arena.winner}`
+
+  return fnv1a(new TextEncoder().encode(canonical))
+}
+
+function sortedById(entities) {
+  return Object.entries(entities).sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0))
+}
+
+function quantize(value) {
+  return Math.floor(value * 100 + 0.5)
+}
+
+function fnv1a(bytes) {
+  let hash = 0x811c9dc5
+  for (const byte of bytes) hash = Math.imul(hash ^ byte, 0x01000193) >>> 0
+  return hash
+}
@@ -6,6 +6,7 @@ export function randomColor() {
 }
 
 export function colorToHex(color) {
+  if (color.startsWith('#')) return parseInt(color.slice(1), 16)
   return {
     blue: 0x00c2cc,
     green: 0x2ee53d,
@@ -17,6 +18,7 @@ export function colorToHex(color) {
 }
 
 export function colorToHexString(color) {
+  if (color.startsWith('#')) return color
   return {
     blue: '#00c2cc',
     green: '#2ee53d',
@@ -0,0 +1,10 @@
+const invitePathPattern = /^\/a\/([a-z]+)\/?$/i
+
+export default function inviteCode() {
+  const match = window.location.pathname.match(invitePathPattern)
+  return match ? match[1].toUpperCase() : null
+}
+
+export function clearInviteCode() {
+  if (inviteCode() !== null) window.history.replaceState(null, '', '/')
+}
@@ -1,37 +1,730 @@
+use anyhow::{Context, Error, anyhow};
+use hyper::Uri;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
 use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use structopt::StructOpt;
 
+use crate::server::{ARENA_MAX_PLAYERS, DEFAULT_TICK_RATE};
+
+/// Ticks shorter than a millisecond can't be scheduled.
+const MAX_TICK_RATE: u64 = 1000;
+/// Admin tokens shorter than this are too easily guessed.
+const ADMIN_TOKEN_MIN_LENGTH: usize = 16;
+/// Printed in place of the admin token, so that printed configs don't give it away.
+const REDACTED: &str = "<redacted>";
+
+#[cfg(debug_assertions)]
+const DEBUG: bool = true;
+#[cfg(not(debug_assertions))]
+const DEBUG: bool = false;
+
+// settings given on the command line (or through the environment) override
+// those in the config file, which in turn override the defaults
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 struct CliConfig {
-    /// Sets the interface to bind to
-    #[structopt(short = "b", long, default_value = "127.0.0.1", env = "BIND_ADDRESS")]
-    bind_address: IpAddr,
+    /// Sets the config file to read settings from
+    #[structopt(short = "c", long, parse(from_os_str), env = "WEBTRON_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Prints the effective configuration (as a config file) and exits
+    #[structopt(long)]
+    print_config: bool,
+
+    /// Sets the interface to bind to [default: 127.0.0.1]
+    #[structopt(short = "b", long, env = "BIND_ADDRESS")]
+    bind_address: Option<IpAddr>,
+
+    /// Sets the port to bind to [default: 3000]
+    #[structopt(short = "p", long, env = "PORT")]
+    port: Option<u16>,
+
+    /// Sets which messages are logged, unless RUST_LOG is set [default: webtron=trace]
+    #[structopt(long, env = "LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// Sets how long (in seconds) to wait for rounds in progress to finish when shutting down [default: 30]
+    #[structopt(long, env = "SHUTDOWN_TIMEOUT")]
+    shutdown_timeout: Option<u64>,
+
+    /// Sets the TLS certificate chain (PEM) to serve HTTPS with, along with --tls-key
+    #[structopt(long, parse(from_os_str), env = "TLS_CERTIFICATE")]
+    tls_certificate: Option<PathBuf>,
+
+    /// Sets the TLS private key (PEM) to serve HTTPS with, along with --tls-certificate
+    #[structopt(long, parse(from_os_str), env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Sets a port to listen for plain HTTP on, redirecting every request to HTTPS
+    #[structopt(long, env = "HTTP_REDIRECT_PORT")]
+    http_redirect_port: Option<u16>,
+
+    /// Sets where the client is served from: embedded, directory or proxy
+    /// [default: directory if a static directory is set, otherwise proxy in debug builds and embedded in release builds]
+    #[structopt(long, possible_values = &FrontendSource::VARIANTS, env = "FRONTEND_SOURCE")]
+    frontend_source: Option<FrontendSource>,
+
+    /// Sets the directory to serve the client from, when the frontend source is directory
+    #[structopt(long, parse(from_os_str), env = "STATIC_DIRECTORY")]
+    static_directory: Option<PathBuf>,
+
+    /// Sets the client dev server to proxy to, when the frontend source is proxy [default: http://localhost:3001]
+    #[structopt(long, env = "PROXY_TARGET")]
+    proxy_target: Option<String>,
+
+    /// Sets the directory to record replays into (replays are not recorded if unset)
+    #[structopt(long, parse(from_os_str), env = "REPLAY_DIRECTORY")]
+    replay_directory: Option<PathBuf>,
+
+    /// Sets the number of replays to keep before the oldest are deleted [default: 100]
+    #[structopt(long, env = "REPLAY_RETENTION")]
+    replay_retention: Option<usize>,
+
+    /// Sets how many turns each player can have waiting to be made on the following ticks [default: 3]
+    #[structopt(long, env = "MAX_QUEUED_TURNS")]
+    max_queued_turns: Option<usize>,
+
+    /// Sets how many arenas can be open at once (unlimited if unset)
+    #[structopt(long, env = "MAX_ARENAS")]
+    max_arenas: Option<usize>,
+
+    /// Sets how many websocket connections each ip address can have open at once (unlimited if unset)
+    #[structopt(long, env = "MAX_CONNECTIONS_PER_IP")]
+    max_connections_per_ip: Option<usize>,
+
+    /// Sets how many players new arenas hold [default: 8]
+    #[structopt(long, env = "ARENA_MAX_PLAYERS")]
+    arena_max_players: Option<usize>,
+
+    /// Sets how many times per second arenas are simulated [default: 20]
+    #[structopt(long, env = "TICK_RATE")]
+    tick_rate: Option<u64>,
+
+    /// Sets how many times per second updates are sent to clients, which must divide the tick rate
+    /// evenly (defaults to the tick rate)
+    #[structopt(long, env = "SEND_RATE")]
+    send_rate: Option<u64>,
+
+    /// Sets how often (in ticks) to send every lightcycle's position to clients for interpolation
+    #[structopt(long, env = "LIGHTCYCLE_SNAPSHOT_INTERVAL")]
+    lightcycle_snapshot_interval: Option<u64>,
+
+    /// Sets the token which admin API requests must carry (the admin API is disabled if unset)
+    #[structopt(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+
+    /// Bans an ip address from connecting (may be given more than once)
+    #[structopt(long = "banned-ip", env = "BANNED_IPS", use_delimiter = true)]
+    banned_ips: Vec<IpAddr>,
+
+    /// Trusts the X-Forwarded-For header in requests from this ip address, such as a reverse proxy's
+    /// (may be given more than once)
+    #[structopt(long = "trusted-proxy", env = "TRUSTED_PROXIES", use_delimiter = true)]
+    trusted_proxies: Vec<IpAddr>,
+
+    /// Masks a word in chat messages (may be given more than once)
+    #[structopt(
+        long = "chat-filter-word",
+        env = "CHAT_FILTER_WORDS",
+        use_delimiter = true
+    )]
+    chat_filter_words: Vec<String>,
+
+    /// Turns arena chat on or off [default: true]
+    #[structopt(long, env = "ENABLE_CHAT")]
+    enable_chat: Option<bool>,
+
+    /// Turns quick match matchmaking on or off [default: true]
+    #[structopt(long, env = "ENABLE_MATCHMAKING")]
+    enable_matchmaking: Option<bool>,
+
+    /// Turns replay recording and playback on or off [default: true]
+    #[structopt(long, env = "ENABLE_REPLAYS")]
+    enable_replays: Option<bool>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+///
+/// Commands which run arenas headlessly instead of starting the server.
+///
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Command {
+    /// Re-simulates a recorded replay and checks that it reproduces the same final state
+    VerifyReplay {
+        /// The replay file to verify
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+
+    /// Runs bot-vs-bot rounds and reports win rates per spawnpoint and average round length
+    Simulate {
+        /// Sets the number of rounds to run
+        #[structopt(short = "r", long, default_value = "1000")]
+        rounds: usize,
+
+        /// Sets the number of bots in each round
+        #[structopt(short = "n", long, default_value = "2")]
+        players: usize,
+
+        /// Overrides the speed of every lightcycle
+        #[structopt(long)]
+        speed: Option<f64>,
+    },
+}
+
+///
+/// The layout of the (toml) config file. Every setting is optional.
+///
+///   bind_address = "0.0.0.0"
+///   port = 3000
+///   log_level = "webtron=info"
+///   shutdown_timeout = 30
+///
+///   [tls]
+///   certificate = "fullchain.pem"
+///   key = "privkey.pem"
+///   http_redirect_port = 80
+///
+///   [game]
+///   tick_rate = 20
+///   send_rate = 20
+///   lightcycle_snapshot_interval = 10
+///
+///   [arena]
+///   max_players = 8
+///
+///   [limits]
+///   max_arenas = 100
+///   max_queued_turns = 3
+///   max_connections_per_ip = 10
+///
+///   [frontend]
+///   source = "directory"
+///   static_directory = "client/dist"
+///   proxy_target = "http://localhost:3001"
+///
+///   [replays]
+///   directory = "replays"
+///   retention = 100
+///
+///   [access]
+///   banned_ips = ["192.0.2.1"]
+///   trusted_proxies = ["127.0.0.1"]
+///
+///   [chat]
+///   filter_words = ["heck"]
+///
+///   [admin]
+///   token = "a long random string"
+///
+///   [features]
+///   chat = true
+///   matchmaking = true
+///   replays = true
+///
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    bind_address: Option<IpAddr>,
+    port: Option<u16>,
+    log_level: Option<String>,
+    shutdown_timeout: Option<u64>,
+    tls: TlsSection,
+    game: GameSection,
+    arena: ArenaSection,
+    limits: LimitsSection,
+    frontend: FrontendSection,
+    replays: ReplaysSection,
+    access: AccessSection,
+    chat: ChatSection,
+    admin: AdminSection,
+    features: FeaturesSection,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TlsSection {
+    certificate: Option<PathBuf>,
+    key: Option<PathBuf>,
+    http_redirect_port: Option<u16>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GameSection {
+    tick_rate: Option<u64>,
+    send_rate: Option<u64>,
+    lightcycle_snapshot_interval: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ArenaSection {
+    max_players: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsSection {
+    max_arenas: Option<usize>,
+    max_queued_turns: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FrontendSection {
+    source: Option<FrontendSource>,
+    static_directory: Option<PathBuf>,
+    proxy_target: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ReplaysSection {
+    directory: Option<PathBuf>,
+    retention: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AccessSection {
+    banned_ips: Option<Vec<IpAddr>>,
+    trusted_proxies: Option<Vec<IpAddr>>,
+}
 
-    /// Sets the port to bind to
-    #[structopt(short = "p", long, default_value = "3000", env = "PORT")]
-    port: u16,
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ChatSection {
+    filter_words: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AdminSection {
+    token: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FeaturesSection {
+    chat: Option<bool>,
+    matchmaking: Option<bool>,
+    replays: Option<bool>,
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read config file {:?}", path))?;
+
+        toml::from_str(&contents).with_context(|| anyhow!("Failed to parse config file {:?}", path))
+    }
 }
 
 #[derive(Debug)]
 pub struct Config {
     pub bind_address: SocketAddr,
+    pub log_level: String,
+    pub shutdown_timeout: Duration,
+    pub tls_certificate: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub http_redirect_port: Option<u16>,
+    pub frontend_source: FrontendSource,
+    pub static_directory: Option<PathBuf>,
+    pub proxy_target: String,
+    pub replay_directory: Option<PathBuf>,
+    pub replay_retention: usize,
+    pub max_queued_turns: usize,
+    pub max_arenas: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub arena_max_players: usize,
+    pub tick_rate: u64,
+    pub send_rate: u64,
+    pub lightcycle_snapshot_interval: Option<u64>,
+    pub banned_ips: Vec<IpAddr>,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub chat_filter_words: Vec<String>,
+    pub admin_token: Option<String>,
+    pub features: Features,
+    pub print_config: bool,
+    pub command: Option<Command>,
+}
+
+///
+/// Where the client is served from.
+///
+/// Embedded serves the client built into the binary, directory serves a client build from disk
+/// (which can be swapped out without recompiling the server), and proxy forwards requests
+/// to the client's dev server.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontendSource {
+    Embedded,
+    Directory,
+    Proxy,
+}
+
+impl FrontendSource {
+    const VARIANTS: [&'static str; 3] = ["embedded", "directory", "proxy"];
+}
+
+impl FromStr for FrontendSource {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.to_lowercase().as_str() {
+            "embedded" => Ok(FrontendSource::Embedded),
+            "directory" => Ok(FrontendSource::Directory),
+            "proxy" => Ok(FrontendSource::Proxy),
+            _ => Err(anyhow!("Unknown frontend source {:?}", source)),
+        }
+    }
+}
+
+/// Parts of the game which can be turned off.
+#[derive(Debug, Clone, Copy)]
+pub struct Features {
+    pub chat: bool,
+    pub matchmaking: bool,
+    pub replays: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             bind_address: SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 3000),
+            log_level: "webtron=trace".to_owned(),
+            shutdown_timeout: Duration::from_secs(30),
+            tls_certificate: None,
+            tls_key: None,
+            http_redirect_port: None,
+            frontend_source: if DEBUG {
+                FrontendSource::Proxy
+            } else {
+                FrontendSource::Embedded
+            },
+            static_directory: None,
+            proxy_target: "http://localhost:3001".to_owned(),
+            replay_directory: None,
+            replay_retention: 100,
+            max_queued_turns: 3,
+            max_arenas: None,
+            max_connections_per_ip: None,
+            arena_max_players: ARENA_MAX_PLAYERS,
+            tick_rate: DEFAULT_TICK_RATE,
+            send_rate: DEFAULT_TICK_RATE,
+            lightcycle_snapshot_interval: None,
+            banned_ips: Vec::new(),
+            trusted_proxies: Vec::new(),
+            chat_filter_words: Vec::new(),
+            admin_token: None,
+            features: Features {
+                chat: true,
+                matchmaking: true,
+                replays: true,
+            },
+            print_config: false,
+            command: None,
         }
     }
 }
 
 impl Config {
-    pub fn new() -> Self {
+    ///
+    /// Reads the configuration from the command line, the environment and the config file,
+    /// and checks that it makes sense.
+    ///
+    pub fn new() -> Result<Self, Error> {
         let cli_config = CliConfig::from_args();
+        let file_config = match &cli_config.config {
+            Some(path) => ConfigFile::read(path)?,
+            None => Default::default(),
+        };
+
+        let config = Self::merge(cli_config, file_config);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn merge(cli: CliConfig, file: ConfigFile) -> Self {
+        let default = Self::default();
+
+        let static_directory = cli.static_directory.or(file.frontend.static_directory);
+        let tick_rate = cli
+            .tick_rate
+            .or(file.game.tick_rate)
+            .unwrap_or(default.tick_rate);
 
         Self {
-            bind_address: SocketAddr::new(cli_config.bind_address, cli_config.port),
+            bind_address: SocketAddr::new(
+                cli.bind_address
+                    .or(file.bind_address)
+                    .unwrap_or_else(|| default.bind_address.ip()),
+                cli.port
+                    .or(file.port)
+                    .unwrap_or(default.bind_address.port()),
+            ),
+            log_level: cli
+                .log_level
+                .or(file.log_level)
+                .unwrap_or(default.log_level),
+            shutdown_timeout: cli
+                .shutdown_timeout
+                .or(file.shutdown_timeout)
+                .map(Duration::from_secs)
+                .unwrap_or(default.shutdown_timeout),
+            tls_certificate: cli.tls_certificate.or(file.tls.certificate),
+            tls_key: cli.tls_key.or(file.tls.key),
+            http_redirect_port: cli.http_redirect_port.or(file.tls.http_redirect_port),
+            frontend_source: cli.frontend_source.or(file.frontend.source).unwrap_or(
+                if static_directory.is_some() {
+                    FrontendSource::Directory
+                } else {
+                    default.frontend_source
+                },
+            ),
+            static_directory,
+            proxy_target: cli
+                .proxy_target
+                .or(file.frontend.proxy_target)
+                .unwrap_or(default.proxy_target),
+            replay_directory: cli.replay_directory.or(file.replays.directory),
+            replay_retention: cli
+                .replay_retention
+                .or(file.replays.retention)
+                .unwrap_or(default.replay_retention),
+            max_queued_turns: cli
+                .max_queued_turns
+                .or(file.limits.max_queued_turns)
+                .unwrap_or(default.max_queued_turns),
+            max_arenas: cli.max_arenas.or(file.limits.max_arenas),
+            max_connections_per_ip: cli
+                .max_connections_per_ip
+                .or(file.limits.max_connections_per_ip),
+            arena_max_players: cli
+                .arena_max_players
+                .or(file.arena.max_players)
+                .unwrap_or(default.arena_max_players),
+            tick_rate,
+            send_rate: cli.send_rate.or(file.game.send_rate).unwrap_or(tick_rate),
+            lightcycle_snapshot_interval: cli
+                .lightcycle_snapshot_interval
+                .or(file.game.lightcycle_snapshot_interval),
+            // lists given on the command line replace (rather than add to) those in the file
+            banned_ips: Some(cli.banned_ips)
+                .filter(|banned_ips| !banned_ips.is_empty())
+                .or(file.access.banned_ips)
+                .unwrap_or(default.banned_ips),
+            trusted_proxies: Some(cli.trusted_proxies)
+                .filter(|trusted_proxies| !trusted_proxies.is_empty())
+                .or(file.access.trusted_proxies)
+                .unwrap_or(default.trusted_proxies),
+            chat_filter_words: Some(cli.chat_filter_words)
+                .filter(|chat_filter_words| !chat_filter_words.is_empty())
+                .or(file.chat.filter_words)
+                .unwrap_or(default.chat_filter_words),
+            admin_token: cli.admin_token.or(file.admin.token),
+            features: Features {
+                chat: cli
+                    .enable_chat
+                    .or(file.features.chat)
+                    .unwrap_or(default.features.chat),
+                matchmaking: cli
+                    .enable_matchmaking
+                    .or(file.features.matchmaking)
+                    .unwrap_or(default.features.matchmaking),
+                replays: cli
+                    .enable_replays
+                    .or(file.features.replays)
+                    .unwrap_or(default.features.replays),
+            },
+            print_config: cli.print_config,
+            command: cli.command,
+        }
+    }
+
+    ///
+    /// Checks every setting, and returns an error listing all of those which are invalid.
+    ///
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+
+        if self.log_level.trim().is_empty() {
+            problems.push("log_level must not be empty".to_owned());
+        }
+        match (&self.tls_certificate, &self.tls_key) {
+            (Some(_), None) | (None, Some(_)) => {
+                problems.push("tls.certificate and tls.key must be set together".to_owned())
+            }
+            (None, None) if self.http_redirect_port.is_some() => problems
+                .push("tls.http_redirect_port needs tls.certificate and tls.key set".to_owned()),
+            _ => {}
+        }
+        for (setting, path) in [
+            ("tls.certificate", &self.tls_certificate),
+            ("tls.key", &self.tls_key),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                problems.push(format!("{} {:?} is not a file", setting, path));
+            }
+        }
+        if self.http_redirect_port == Some(self.bind_address.port()) {
+            problems.push("tls.http_redirect_port must differ from port".to_owned());
+        }
+        match &self.static_directory {
+            Some(directory) if !directory.is_dir() => problems.push(format!(
+                "frontend.static_directory {:?} is not a directory",
+                directory
+            )),
+            None if self.frontend_source == FrontendSource::Directory => problems.push(
+                "frontend.static_directory must be set when the frontend source is directory"
+                    .to_owned(),
+            ),
+            _ => {}
+        }
+        match self.proxy_target.parse::<Uri>() {
+            Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => {}
+            _ => problems.push(format!(
+                "frontend.proxy_target {:?} is not an absolute url",
+                self.proxy_target
+            )),
+        }
+        if self.replay_retention < 1 {
+            problems.push("replays.retention must be at least 1".to_owned());
+        }
+        if self.max_queued_turns < 1 {
+            problems.push("limits.max_queued_turns must be at least 1".to_owned());
+        }
+        if self.max_arenas == Some(0) {
+            problems.push("limits.max_arenas must be at least 1".to_owned());
+        }
+        if self.max_connections_per_ip == Some(0) {
+            problems.push("limits.max_connections_per_ip must be at least 1".to_owned());
+        }
+        if !(1..=ARENA_MAX_PLAYERS).contains(&self.arena_max_players) {
+            problems.push(format!(
+                "arena.max_players must be between 1 and {}",
+                ARENA_MAX_PLAYERS
+            ));
+        }
+        if !(1..=MAX_TICK_RATE).contains(&self.tick_rate) {
+            problems.push(format!(
+                "game.tick_rate must be between 1 and {}",
+                MAX_TICK_RATE
+            ));
+        }
+        if !(1..=self.tick_rate).contains(&self.send_rate) {
+            problems.push("game.send_rate must be between 1 and the tick rate".to_owned());
+        } else if !self.tick_rate.is_multiple_of(self.send_rate) {
+            // updates can only be sent on whole ticks, so any other rate couldn't be kept to
+            problems.push("game.send_rate must divide the tick rate evenly".to_owned());
+        }
+        if self.lightcycle_snapshot_interval == Some(0) {
+            problems.push("game.lightcycle_snapshot_interval must be at least 1".to_owned());
         }
+        if let Some(admin_token) = &self.admin_token
+            && admin_token.trim().len() < ADMIN_TOKEN_MIN_LENGTH
+        {
+            problems.push(format!(
+                "admin.token must be at least {} characters",
+                ADMIN_TOKEN_MIN_LENGTH
+            ));
+        }
+
+        if !problems.is_empty() {
+            return Err(anyhow!(
+                "Invalid configuration:\n  {}",
+                problems.join("\n  ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configuration in the format of a config file (with the admin token redacted).
+    pub fn to_toml(&self) -> Result<String, Error> {
+        let file = ConfigFile {
+            bind_address: Some(self.bind_address.ip()),
+            port: Some(self.bind_address.port()),
+            log_level: Some(self.log_level.clone()),
+            shutdown_timeout: Some(self.shutdown_timeout.as_secs()),
+            tls: TlsSection {
+                certificate: self.tls_certificate.clone(),
+                key: self.tls_key.clone(),
+                http_redirect_port: self.http_redirect_port,
+            },
+            game: GameSection {
+                tick_rate: Some(self.tick_rate),
+                send_rate: Some(self.send_rate),
+                lightcycle_snapshot_interval: self.lightcycle_snapshot_interval,
+            },
+            arena: ArenaSection {
+                max_players: Some(self.arena_max_players),
+            },
+            limits: LimitsSection {
+                max_arenas: self.max_arenas,
+                max_queued_turns: Some(self.max_queued_turns),
+                max_connections_per_ip: self.max_connections_per_ip,
+            },
+            frontend: FrontendSection {
+                source: Some(self.frontend_source),
+                static_directory: self.static_directory.clone(),
+                proxy_target: Some(self.proxy_target.clone()),
+            },
+            replays: ReplaysSection {
+                directory: self.replay_directory.clone(),
+                retention: Some(self.replay_retention),
+            },
+            access: AccessSection {
+                banned_ips: Some(self.banned_ips.clone()),
+                trusted_proxies: Some(self.trusted_proxies.clone()),
+            },
+            chat: ChatSection {
+                filter_words: Some(self.chat_filter_words.clone()),
+            },
+            admin: AdminSection {
+                token: self.admin_token.as_ref().map(|_| REDACTED.to_owned()),
+            },
+            features: FeaturesSection {
+                chat: Some(self.features.chat),
+                matchmaking: Some(self.features.matchmaking),
+                replays: Some(self.features.replays),
+            },
+        };
+
+        toml::to_string(&file).context("Failed to serialize configuration")
+    }
+
+    /// The time between simulation ticks.
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.tick_seconds())
+    }
+
+    ///
+    /// The length of each simulation tick, in seconds.
+    ///
+    /// The simulation is stepped by exactly this much each tick (rather than by the
+    /// tick duration, which is rounded to whole nanoseconds), and replays record it
+    /// so that they can be re-simulated with the same steps.
+    ///
+    pub fn tick_seconds(&self) -> f64 {
+        1.0 / self.tick_rate as f64
+    }
+
+    /// How many simulation ticks pass between each send of updates to clients.
+    pub fn ticks_per_send(&self) -> u64 {
+        (self.tick_rate / self.send_rate).max(1)
     }
 }
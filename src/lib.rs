@@ -8,11 +8,26 @@ pub mod macros;
 ///
 pub mod config;
 
+///
+/// Collects metrics about the running server.
+///
+pub mod metrics;
+
 ///
 /// Handles serverside game logic.
 ///
 pub mod server;
 
+///
+/// Runs arenas headlessly, for verifying replays and testing game balance.
+///
+pub mod simulation;
+
+///
+/// Handles signals sent to the server process.
+///
+pub mod signals;
+
 ///
 /// Serves the client and handles websocket connections.
 ///
@@ -21,24 +36,60 @@ pub mod web;
 use anyhow::{Context, Error};
 use std::sync::Arc;
 use tokio::sync::mpsc;
-use tokio::try_join;
+use tokio::{select, task};
 
 use config::Config;
-use server::Server as WebtronServer;
+use metrics::Metrics;
+use server::{BanList, Heartbeat, Server as WebtronServer};
+use signals::ShutdownState;
 
 ///
 /// Starts up webtron server.
 ///
-pub async fn start() -> Result<(), Error> {
-    let config = Arc::new(Config::new());
+pub async fn start(config: Config) -> Result<(), Error> {
+    if config.print_config {
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
 
-    let (server_tx, server_rx) = mpsc::channel(100);
-    let server = tokio::spawn(WebtronServer::new(server_rx).start());
-    let web = tokio::spawn(web::start(server_tx, config));
+    let config = Arc::new(config);
+
+    if let Some(command) = config.command.clone() {
+        return task::spawn_blocking(move || simulation::run(command))
+            .await
+            .context("Failure occurred in simulation task")?;
+    }
 
-    try_join!(server, web).context("Failure occurred in task")?;
+    let metrics = Arc::new(Metrics::new()?);
+    let heartbeat = Arc::new(Heartbeat::new(config.tick_duration()));
+    let bans = Arc::new(BanList::new(config.banned_ips.iter().copied()));
+    let (server_tx, server_rx) = mpsc::channel(100);
+    let shutdown = Arc::new(ShutdownState::default());
+    tokio::spawn(signals::handle_shutdown_signals(
+        shutdown.clone(),
+        server_tx.clone(),
+        config.shutdown_timeout,
+    ));
+    let server = tokio::spawn(
+        WebtronServer::new(
+            server_rx,
+            server_tx.clone(),
+            config.clone(),
+            metrics.clone(),
+            heartbeat.clone(),
+            bans.clone(),
+        )
+        .start(),
+    );
+    let web = tokio::spawn(web::start(
+        server_tx, config, metrics, heartbeat, bans, shutdown,
+    ));
 
-    Ok(())
+    // the server task finishes once it has shut down, taking the web server down with it
+    select! {
+        server = server => server.context("Failure occurred in server task"),
+        web = web => web.context("Failure occurred in web task")?,
+    }
 }
 
 ///
@@ -34,6 +34,14 @@ macro_rules! new_id_type {
             }
         }
 
+        impl std::str::FromStr for $ident {
+            type Err = uuid::Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                uuid::Uuid::parse_str(s).map(Self)
+            }
+        }
+
         impl std::ops::Deref for $ident {
             type Target = uuid::Uuid;
 
@@ -1,17 +1,24 @@
 use log::error;
 use std::env;
+use webtron::config::Config;
 use webtron::get_error_chain;
 
 #[tokio::main]
 #[quit::main]
 async fn main() {
+    // the logger isn't set up until the log level has been read from the config
+    let config = Config::new().unwrap_or_else(|error| {
+        eprintln!("{}", get_error_chain(error));
+        quit::with_code(1);
+    });
+
     if env::var_os("RUST_LOG").is_none() {
         // TODO: Audit that the environment access only happens in single-threaded code.
-        unsafe { env::set_var("RUST_LOG", "webtron=trace") };
+        unsafe { env::set_var("RUST_LOG", &config.log_level) };
     }
     pretty_env_logger::init();
 
-    webtron::start().await.unwrap_or_else(|error| {
+    webtron::start(config).await.unwrap_or_else(|error| {
         error!("{}", get_error_chain(error));
         quit::with_code(1);
     });
@@ -0,0 +1,161 @@
+use anyhow::{Context, Error, anyhow};
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+///
+/// Metrics describing the running server, exported in the prometheus text format.
+///
+/// Every metric is an atomic (or a map of atomics, keyed by label),
+/// so they can be recorded from the server and websocket tasks without waiting on each other.
+///
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+
+    /// Clients currently connected.
+    pub clients: IntGauge,
+    /// Arenas currently open, labelled by `state` (waiting, playing or finished).
+    pub arenas: IntGaugeVec,
+    /// Players in each open arena, labelled by `arena` id.
+    pub arena_players: IntGaugeVec,
+    /// Time spent processing each server tick, in seconds.
+    pub tick_duration: Histogram,
+
+    /// Messages received from clients, labelled by `type`.
+    pub messages_in: IntCounterVec,
+    /// Messages sent to clients, labelled by `type`.
+    pub messages_out: IntCounterVec,
+    /// Bytes of serialized messages sent to clients, labelled by `type`.
+    pub messages_out_bytes: IntCounterVec,
+    /// Messages the server failed to hand to a client's websocket, labelled by `type`.
+    pub send_failures: IntCounterVec,
+    /// Messages received from clients which could not be parsed.
+    pub parse_failures: IntCounter,
+    /// Messages from clients dropped for going over their rate limits, labelled by `type`.
+    pub rate_limited: IntCounterVec,
+    /// Clients disconnected for repeatedly going over their rate limits.
+    pub rate_limit_disconnects: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, Error> {
+        let registry = Registry::new_custom(Some("webtron".to_owned()), None)
+            .context("Failed to create metrics registry")?;
+
+        Ok(Self {
+            clients: register(
+                &registry,
+                IntGauge::new("clients", "Clients currently connected"),
+            )?,
+            arenas: register(
+                &registry,
+                IntGaugeVec::new(Opts::new("arenas", "Arenas currently open"), &["state"]),
+            )?,
+            arena_players: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new("arena_players", "Players in each open arena"),
+                    &["arena"],
+                ),
+            )?,
+            tick_duration: register(
+                &registry,
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "tick_duration_seconds",
+                        "Time spent processing each server tick",
+                    )
+                    .buckets(vec![
+                        0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+                    ]),
+                ),
+            )?,
+            messages_in: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("messages_in_total", "Messages received from clients"),
+                    &["type"],
+                ),
+            )?,
+            messages_out: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("messages_out_total", "Messages sent to clients"),
+                    &["type"],
+                ),
+            )?,
+            messages_out_bytes: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "messages_out_bytes_total",
+                        "Bytes of serialized messages sent to clients",
+                    ),
+                    &["type"],
+                ),
+            )?,
+            send_failures: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "send_failures_total",
+                        "Messages the server failed to hand to a client's websocket",
+                    ),
+                    &["type"],
+                ),
+            )?,
+            parse_failures: register(
+                &registry,
+                IntCounter::new(
+                    "parse_failures_total",
+                    "Messages received from clients which could not be parsed",
+                ),
+            )?,
+            rate_limited: register(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "rate_limited_total",
+                        "Messages from clients dropped for going over their rate limits",
+                    ),
+                    &["type"],
+                ),
+            )?,
+            rate_limit_disconnects: register(
+                &registry,
+                IntCounter::new(
+                    "rate_limit_disconnects_total",
+                    "Clients disconnected for repeatedly going over their rate limits",
+                ),
+            )?,
+            registry,
+        })
+    }
+
+    ///
+    /// Encodes every metric in the prometheus text format.
+    ///
+    pub fn encode(&self) -> Result<String, Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("Failed to encode metrics")?;
+
+        String::from_utf8(buffer).context("Failed to encode metrics")
+    }
+}
+
+fn register<T: Collector + Clone + 'static>(
+    registry: &Registry,
+    collector: prometheus::Result<T>,
+) -> Result<T, Error> {
+    let collector = collector.context("Failed to create metric")?;
+    registry
+        .register(Box::new(collector.clone()))
+        .with_context(|| anyhow!("Failed to register metric {:?}", collector.desc()))?;
+
+    Ok(collector)
+}
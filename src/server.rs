@@ -1,47 +1,152 @@
+mod admin;
 mod arena;
+mod bans;
+mod chat;
+mod heartbeat;
+mod matchmaking;
 mod messages;
 mod primitives;
+mod replay;
 
 use anyhow::{Context as ResultContext, Error, anyhow};
-use log::{error, info, warn};
+use chrono::Utc;
+use log::{debug, error, info, warn};
 use std::collections::HashMap;
-use std::time::Duration;
-use tokio::sync::mpsc::Receiver;
-use tokio::time;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::{task, time};
 
-pub use arena::{Arena, ArenaInput, ArenaOverview, ArenaUpdate};
-pub use messages::{MessageIn, MessageOut};
+pub use admin::{AdminCommand, AdminError, AdminReply, ClientOverview, RuntimeSettings};
+pub use arena::{
+    ARENA_LAG_COMPENSATION_MAX_TICKS, ARENA_MAX_PLAYERS, Arena, ArenaInput, ArenaOverview,
+    ArenaUpdate, EntityMap, InputOutcome, Lightcycle, Lightribbon, ProcessedInput,
+    generate_arena_code,
+};
+pub use bans::{Ban, BanList};
+pub use chat::{Chat, ChatFilter, ChatMessage, WordListFilter};
+pub use heartbeat::Heartbeat;
+pub use matchmaking::{MatchPreferences, MatchmakingQueue};
+pub use messages::outgoing::{ArenaStatePatch, InputRejectedReason};
+pub(crate) use messages::{CLOSE_CODE_GOING_AWAY, CLOSE_CODE_NORMAL, CLOSE_CODE_POLICY_VIOLATION};
+pub use messages::{MessageIn, MessageOut, ServerQuery, ServerStatus};
 pub use primitives::*;
+pub use replay::{
+    Replay, ReplayControl, ReplayFrame, ReplayHeader, ReplayPlayback, ReplayRecorder, list_replays,
+};
 
+use crate::config::Config;
 use crate::get_error_chain;
+use crate::metrics::Metrics;
 use messages::MessageInPayload;
+use messages::incoming::Turn;
 
-const UPDATE_RATE_MILLISECONDS: u64 = 50; // 1000 / 50 = 20 updates per second
+/// The tick rate (in ticks per second) used when none is configured.
+pub const DEFAULT_TICK_RATE: u64 = 20;
+/// How long to wait for clients to disconnect once their websockets have been closed.
+const SHUTDOWN_CLOSE_GRACE_SECONDS: u64 = 5;
+/// Desync reports which arrive sooner than this after the state was last resent are ignored,
+/// so that they can't be used to make the server send more than it otherwise would.
+const DESYNC_RESEND_MIN_SECONDS: u64 = 5;
 
 #[derive(Debug)]
 pub struct Server {
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    heartbeat: Arc<Heartbeat>,
     message_queue: Receiver<MessageIn>,
+    /// Posts the results of background tasks (such as loading replays) back into the queue.
+    message_queue_tx: Sender<MessageIn>,
     clients: HashMap<ClientId, Client>,
     arenas: HashMap<ArenaId, Arena>,
+    matchmaking: MatchmakingQueue,
+    chat: Chat,
+    replay_recorder: ReplayRecorder,
+    replay_playbacks: HashMap<ClientId, ReplayPlayback>,
+    /// The replay each client is waiting on to be loaded, if any.
+    replay_loads: HashMap<ClientId, ReplayId>,
+    replay_saves_in_progress: Arc<AtomicUsize>,
+    shutdown: Option<ShutdownProgress>,
+    started_at: Instant,
+    /// Starts out as configured, but can be changed by admins.
+    max_arenas: Option<usize>,
+    bans: Arc<BanList>,
+}
+
+#[derive(Debug)]
+struct ShutdownProgress {
+    reason: String,
+    deadline: Instant,
+    clients_closed_at: Option<Instant>,
 }
 
 impl Server {
-    pub fn new(message_queue: Receiver<MessageIn>) -> Self {
+    pub fn new(
+        message_queue: Receiver<MessageIn>,
+        message_queue_tx: Sender<MessageIn>,
+        config: Arc<Config>,
+        metrics: Arc<Metrics>,
+        heartbeat: Arc<Heartbeat>,
+        bans: Arc<BanList>,
+    ) -> Self {
         Self {
+            replay_recorder: ReplayRecorder::new(config.tick_rate),
+            max_arenas: config.max_arenas,
+            chat: Chat::with_filter(WordListFilter::new(&config.chat_filter_words)),
+            config,
+            metrics,
+            heartbeat,
             message_queue,
+            message_queue_tx,
             clients: Default::default(),
             arenas: Default::default(),
+            matchmaking: Default::default(),
+            replay_playbacks: Default::default(),
+            replay_loads: Default::default(),
+            replay_saves_in_progress: Default::default(),
+            shutdown: None,
+            started_at: Instant::now(),
+            bans,
         }
     }
 
     pub async fn start(mut self) {
-        let mut interval = time::interval(Duration::from_millis(UPDATE_RATE_MILLISECONDS));
+        let tick_duration = self.config.tick_duration();
+        let tick_seconds = self.config.tick_seconds();
+        let ticks_per_send = self.config.ticks_per_send();
+
+        let mut interval = time::interval(tick_duration);
+        let mut ticks_since_send = 0;
         loop {
             interval.tick().await;
+            self.heartbeat.beat();
+            // observed when dropped, at the end of the tick
+            let _tick_timer = self.metrics.tick_duration.start_timer();
+
             self.process_messages().await;
-            self.update(UPDATE_RATE_MILLISECONDS as f64 / 1000.0);
+            if self.process_shutdown().await {
+                break;
+            }
+            self.process_matchmaking().await;
+            self.update(tick_seconds);
+            self.send_input_outcomes().await;
+
+            // updates from the ticks in between sends are batched into a single patch
+            ticks_since_send += 1;
+            if ticks_since_send < ticks_per_send {
+                continue;
+            }
+            ticks_since_send = 0;
+
             self.send_updates().await;
+            self.send_replay_updates(tick_seconds * ticks_per_send as f64)
+                .await;
+            self.record_metrics();
         }
+
+        info!("Server stopped");
     }
 
     pub async fn process_messages(&mut self) {
@@ -57,18 +162,98 @@ impl Server {
         }
     }
 
+    pub async fn process_matchmaking(&mut self) {
+        if self.matchmaking.is_empty() || self.shutdown.is_some() {
+            return;
+        }
+
+        // fill existing arenas which haven't started yet
+        let arena_ids: Vec<ArenaId> = self.arenas.keys().copied().collect();
+        for arena_id in arena_ids {
+            self.fill_arena_from_matchmaking(arena_id).await;
+        }
+
+        // open new arenas for groups of queued players
+        while let Some(queued) = self.matchmaking.ready_group() {
+            let name = queued.player.name.clone();
+            let size = queued.preferences.size;
+
+            let arena_id = match self.new_arena(&name) {
+                Ok(arena_id) => arena_id,
+                Err(error) => {
+                    warn!("Failed to open arena for quick match: {}", error);
+                    break;
+                }
+            };
+            if let (Some(arena), Some(size)) = (self.arenas.get_mut(&arena_id), size) {
+                arena.set_max_players(size);
+            }
+
+            if self.fill_arena_from_matchmaking(arena_id).await == 0 {
+                self.arenas.remove(&arena_id);
+                break;
+            }
+        }
+
+        // let everybody still waiting know where they are in the queue
+        for (client_id, position) in self.matchmaking.take_position_changes() {
+            let client = match self.clients.get_mut(&client_id) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            if let Err(error) = client
+                .tx
+                .send(MessageOut::QuickMatchPosition(position))
+                .await
+            {
+                error!("Failed to send QuickMatchPosition to client: {}", error);
+            }
+        }
+    }
+
     pub fn update(&mut self, delta_time: f64) {
-        self.arenas.retain(|_, arena| {
+        let config = &self.config;
+        let chat = &mut self.chat;
+        let replay_recorder = &mut self.replay_recorder;
+        let replay_saves_in_progress = &self.replay_saves_in_progress;
+
+        self.arenas.retain(|id, arena| {
             arena.update(delta_time);
 
+            if config.features.replays
+                && let Some(replay_directory) = &config.replay_directory
+                && let Some(replay) = replay_recorder.observe(arena)
+            {
+                replay.save_in_background(
+                    replay_directory.clone(),
+                    config.replay_retention,
+                    replay_saves_in_progress.clone(),
+                );
+            }
+
             // discard arena if all players have left
-            !arena.players.is_empty()
+            if arena.players.is_empty() {
+                chat.remove_arena(*id);
+                replay_recorder.remove_arena(*id);
+                return false;
+            }
+
+            true
         })
     }
 
     pub async fn send_updates(&mut self) {
         let clients = &mut self.clients;
         let arenas = &self.arenas;
+        let chat = &self.chat;
+        let send_failures = &self.metrics.send_failures;
+        let (tick_rate, send_rate) = (self.config.tick_rate, self.config.send_rate);
+        let ticks_per_send = self.config.ticks_per_send();
+        let snapshot_interval = self
+            .config
+            .lightcycle_snapshot_interval
+            .filter(|interval| *interval > 0);
 
         for client in clients.values_mut() {
             let arena = match client.arena {
@@ -88,45 +273,437 @@ impl Server {
                     .await
                 {
                     error!("Failed to send ArenaState to client: {}", error);
+                    send_failures.with_label_values(&["ArenaState"]).inc();
                     continue;
                 }
+                if let Err(error) = client
+                    .tx
+                    .send(MessageOut::ChatHistory(chat.history(arena.id)))
+                    .await
+                {
+                    error!("Failed to send ChatHistory to client: {}", error);
+                    send_failures.with_label_values(&["ChatHistory"]).inc();
+                }
                 client.updates_sent_so_far = arena.updates.len();
+                client.resend_state = false;
+                continue;
+            }
+
+            // the state includes every update so far, so none are sent alongside it
+            if client.resend_state {
+                if let Err(error) = client
+                    .tx
+                    .send(MessageOut::ArenaState(Box::from(arena.clone())))
+                    .await
+                {
+                    error!("Failed to send ArenaState to client: {}", error);
+                    send_failures.with_label_values(&["ArenaState"]).inc();
+                    continue;
+                }
+                client.updates_sent_so_far = arena.updates.len();
+                client.resend_state = false;
                 continue;
             }
 
             if client.updates_sent_so_far < arena.updates.len() {
                 if let Err(error) = client
                     .tx
-                    .send(MessageOut::ArenaStatePatch(
-                        arena
+                    .send(MessageOut::ArenaStatePatch(ArenaStatePatch {
+                        tick: arena.tick,
+                        timestamp: Utc::now(),
+                        tick_rate,
+                        send_rate,
+                        updates: arena
                             .updates
                             .iter()
                             .skip(client.updates_sent_so_far)
                             .cloned()
                             .collect(),
-                    ))
+                    }))
                     .await
                 {
                     error!("Failed to send ArenaStatePatch to client: {}", error);
+                    send_failures.with_label_values(&["ArenaStatePatch"]).inc();
                     continue;
                 }
                 client.updates_sent_so_far = arena.updates.len();
             }
+
+            if let Some(interval) = snapshot_interval
+                && arena.started.is_some()
+                && arena.tick > 0
+                && arena.tick % interval < ticks_per_send
+                && let Err(error) = client
+                    .tx
+                    .send(MessageOut::LightcycleSnapshot {
+                        tick: arena.tick,
+                        timestamp: Utc::now(),
+                        lightcycles: arena.lightcycles.clone(),
+                    })
+                    .await
+            {
+                error!("Failed to send LightcycleSnapshot to client: {}", error);
+                send_failures
+                    .with_label_values(&["LightcycleSnapshot"])
+                    .inc();
+            }
+        }
+    }
+
+    ///
+    /// Tells clients which of their sequenced inputs were made (and on which tick),
+    /// and which were refused or dropped.
+    ///
+    pub async fn send_input_outcomes(&mut self) {
+        let clients = &mut self.clients;
+        let send_failures = &self.metrics.send_failures;
+
+        for arena in self.arenas.values_mut() {
+            for processed in arena.take_processed_inputs() {
+                let client = match clients.values_mut().find(|client| {
+                    client.arena == Some(arena.id) && client.player == Some(processed.player_id)
+                }) {
+                    Some(client) => client,
+                    None => continue,
+                };
+
+                let message = match processed.outcome {
+                    InputOutcome::Applied(tick) => MessageOut::InputAck {
+                        sequence: processed.sequence,
+                        tick,
+                    },
+                    InputOutcome::Dropped => MessageOut::InputRejected {
+                        sequence: processed.sequence,
+                        reason: InputRejectedReason::Dropped,
+                    },
+                };
+                let name = message.name();
+                if let Err(error) = client.tx.send(message).await {
+                    error!("Failed to send {} to client: {}", name, error);
+                    send_failures.with_label_values(&[name]).inc();
+                }
+            }
+        }
+    }
+
+    ///
+    /// Records the number of clients, arenas and players.
+    /// Runs once per send rather than every tick, as labelled gauges are rebuilt from scratch.
+    ///
+    pub fn record_metrics(&self) {
+        let metrics = &self.metrics;
+        metrics.clients.set(self.clients.len() as i64);
+
+        metrics.arenas.reset();
+        metrics.arena_players.reset();
+        for state in ["waiting", "playing", "finished"] {
+            metrics.arenas.with_label_values(&[state]).set(0);
+        }
+        for arena in self.arenas.values() {
+            let state = match (arena.started, arena.winner) {
+                (None, _) => "waiting",
+                (Some(_), None) => "playing",
+                (Some(_), Some(_)) => "finished",
+            };
+            metrics.arenas.with_label_values(&[state]).inc();
+            metrics
+                .arena_players
+                .with_label_values(&[&arena.id.to_string()])
+                .set(arena.players.len() as i64);
+        }
+    }
+}
+
+impl Server {
+    pub async fn send_replay_updates(&mut self, delta_time: f64) {
+        let send_rate = self.config.send_rate;
+
+        for (client_id, playback) in self.replay_playbacks.iter_mut() {
+            let updates = playback.advance(delta_time);
+            // finished playbacks are kept (paused at the end) so that they can be seeked back
+            // through, until the client stops watching
+            if playback.is_finished() {
+                playback.pause();
+            }
+            if updates.is_empty() {
+                continue;
+            }
+
+            let client = match self.clients.get_mut(client_id) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            if let Err(error) = client
+                .tx
+                .send(MessageOut::ArenaStatePatch(ArenaStatePatch {
+                    tick: playback.tick(),
+                    timestamp: Utc::now(),
+                    tick_rate: playback.tick_rate(),
+                    send_rate,
+                    updates,
+                }))
+                .await
+            {
+                error!("Failed to send replay ArenaStatePatch to client: {}", error);
+            }
         }
     }
+
+    pub async fn client_watch_replay(
+        &mut self,
+        client_id: ClientId,
+        replay_id: ReplayId,
+    ) -> Result<(), Error> {
+        let replay_directory = self
+            .config
+            .replay_directory
+            .clone()
+            .filter(|_| self.config.features.replays)
+            .ok_or_else(|| anyhow!("Replays are not enabled on this server"))?;
+
+        // read off the server loop, so that arenas keep ticking while the replay loads
+        self.replay_loads.insert(client_id, replay_id);
+        let mut message_queue_tx = self.message_queue_tx.clone();
+        tokio::spawn(async move {
+            let replay = task::spawn_blocking(move || {
+                Replay::read(&Replay::path(&replay_directory, replay_id))
+            })
+            .await
+            .with_context(|| anyhow!("Failed to load replay {}", replay_id))
+            .and_then(|replay| replay);
+
+            if let Err(error) = message_queue_tx
+                .send(MessageIn::replay_loaded(client_id, replay_id, replay))
+                .await
+            {
+                error!("Failed to send loaded replay to server: {}", error);
+            }
+        });
+
+        Ok(())
+    }
+
+    ///
+    /// Starts playing a replay once it has been loaded,
+    /// unless the client has since asked for something else.
+    ///
+    pub async fn client_replay_loaded(
+        &mut self,
+        client_id: ClientId,
+        replay_id: ReplayId,
+        replay: Result<Replay, Error>,
+    ) -> Result<(), Error> {
+        if self.replay_loads.get(&client_id) != Some(&replay_id) {
+            return Ok(());
+        }
+        self.replay_loads.remove(&client_id);
+        let replay = replay?;
+
+        self.matchmaking.remove(client_id);
+        self.client_part_arena(client_id)
+            .unwrap_or_else(|error| warn!("Failed to remove client from their arena: {}", error));
+
+        let client = self
+            .clients
+            .get_mut(&client_id)
+            .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+        client.player = None;
+        client.arena = None;
+
+        let playback = ReplayPlayback::new(replay);
+
+        // the client is joined to the recorded arena as a spectator
+        client
+            .tx
+            .send(MessageOut::ArenaJoined(
+                playback.state().id,
+                PlayerId::default(),
+                Player::default(),
+            ))
+            .await
+            .with_context(|| anyhow!("Failed to send ArenaJoined to client {}", client_id))?;
+        client
+            .tx
+            .send(MessageOut::ArenaState(Box::from(playback.state().clone())))
+            .await
+            .with_context(|| anyhow!("Failed to send ArenaState to client {}", client_id))?;
+
+        self.replay_playbacks.insert(client_id, playback);
+        info!("Client {} is watching replay {}", client_id, replay_id);
+
+        Ok(())
+    }
+
+    pub async fn client_control_replay(
+        &mut self,
+        client_id: ClientId,
+        control: ReplayControl,
+    ) -> Result<(), Error> {
+        let playback = self
+            .replay_playbacks
+            .get_mut(&client_id)
+            .with_context(|| anyhow!("Client {} is not watching a replay", client_id))?;
+
+        match control {
+            ReplayControl::Pause => playback.pause(),
+            ReplayControl::Resume => playback.resume(),
+            ReplayControl::SetSpeed(speed) => playback.set_speed(speed),
+            ReplayControl::Seek(milliseconds) => {
+                let state = playback.seek(milliseconds).clone();
+
+                let client = self
+                    .clients
+                    .get_mut(&client_id)
+                    .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+                client
+                    .tx
+                    .send(MessageOut::ArenaState(Box::from(state)))
+                    .await
+                    .with_context(|| {
+                        anyhow!("Failed to send ArenaState to client {}", client_id)
+                    })?;
+            }
+            ReplayControl::Stop => self.client_stop_replay(client_id),
+        }
+
+        Ok(())
+    }
+
+    /// Stops the replay the client is watching (or waiting on), if any.
+    pub fn client_stop_replay(&mut self, client_id: ClientId) {
+        self.replay_playbacks.remove(&client_id);
+        self.replay_loads.remove(&client_id);
+    }
 }
 
 impl Server {
-    pub fn new_arena(&mut self, name: &str) -> ArenaId {
-        let arena = Arena::with_name(name);
+    pub fn new_arena(&mut self, name: &str) -> Result<ArenaId, Error> {
+        if self.shutdown.is_some() {
+            return Err(anyhow!("Server is shutting down"));
+        }
+        if let Some(max_arenas) = self.max_arenas
+            && self.arenas.len() >= max_arenas
+        {
+            return Err(anyhow!("Server already has {} arenas open", max_arenas));
+        }
+
+        let mut arena = Arena::with_name(name);
+        arena.set_max_players(self.config.arena_max_players);
+        arena.set_max_queued_turns(self.config.max_queued_turns);
+        while self.find_arena_by_code(&arena.code).is_some() {
+            arena.code = generate_arena_code();
+        }
         let id = arena.id;
 
         self.arenas.insert(id, arena);
 
-        id
+        Ok(id)
+    }
+
+    pub fn find_arena_by_code(&self, code: &str) -> Option<ArenaId> {
+        let code = code.trim().to_uppercase();
+        self.arenas
+            .values()
+            .find(|arena| arena.code == code)
+            .map(|arena| arena.id)
     }
 
-    pub fn client_input(&mut self, client_id: ClientId, input: ArenaInput) -> Result<(), Error> {
+    /// Moves queued players into the given arena until it is full or no suitable players remain.
+    /// Returns the number of players who joined.
+    async fn fill_arena_from_matchmaking(&mut self, arena_id: ArenaId) -> usize {
+        let mut joined = 0;
+
+        while let Some(queued) = self
+            .arenas
+            .get(&arena_id)
+            .and_then(|arena| self.matchmaking.take_for_arena(arena))
+        {
+            match self
+                .client_join_arena(queued.client_id, queued.player, Some(arena_id))
+                .await
+            {
+                Ok(()) => joined += 1,
+                Err(error) => error!(
+                    "Failed to join queued client to arena: {}",
+                    get_error_chain(error)
+                ),
+            }
+        }
+
+        joined
+    }
+
+    pub async fn client_join_arena(
+        &mut self,
+        client_id: ClientId,
+        player: Player,
+        arena_id: Option<ArenaId>,
+    ) -> Result<(), Error> {
+        // normalized before anything else, so that an invalid name can't leave behind an arena
+        let player = player.normalized()?;
+
+        self.matchmaking.remove(client_id);
+        self.client_stop_replay(client_id);
+        self.client_part_arena(client_id)
+            .unwrap_or_else(|error| warn!("Failed to remove client from their arena: {}", error));
+
+        let arena_id = match arena_id {
+            Some(arena_id) => {
+                if self.arenas.contains_key(&arena_id) {
+                    arena_id
+                } else {
+                    self.new_arena(&player.name)?
+                }
+            }
+            None => self.new_arena(&player.name)?,
+        };
+
+        let arena = self
+            .arenas
+            .get_mut(&arena_id)
+            .ok_or_else(|| anyhow!("Arena {} not found", arena_id))?;
+
+        if arena.players.len() >= arena.max_players {
+            return Err(anyhow!("Arena {} is full", arena_id));
+        }
+
+        let player = arena.resolve_player_conflicts(player);
+        let player_id = player.id;
+
+        let client = self
+            .clients
+            .get_mut(&client_id)
+            .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+        arena.add_player(player.clone());
+        if let Some(latency) = client.latency_milliseconds {
+            arena.set_player_ping(player_id, latency);
+        }
+        // apply the new player now so that later joins this tick see them
+        arena.apply_updates();
+        client.player = Some(player_id);
+        client.arena = Some(arena_id);
+
+        client
+            .tx
+            .send(MessageOut::ArenaJoined(arena.id, player_id, player))
+            .await
+            .with_context(|| anyhow!("Failed to send ArenaJoined to client {}", client_id))?;
+
+        info!("Player {} joined arena {}", player_id, arena_id);
+
+        Ok(())
+    }
+
+    pub fn client_input(
+        &mut self,
+        client_id: ClientId,
+        input: ArenaInput,
+        sequence: Option<u32>,
+    ) -> Result<(), Error> {
         let client = self
             .clients
             .get(&client_id)
@@ -145,11 +722,49 @@ impl Server {
             .player
             .with_context(|| anyhow!("Client {} has no player", client_id))?;
 
-        arena.process_input(player_id, input);
+        arena.process_sequenced_input_at(Utc::now(), player_id, input, sequence);
 
         Ok(())
     }
 
+    ///
+    /// Processes an input tagged with a client sequence number, which is acknowledged
+    /// (by send_input_outcomes) once it has been made or dropped.
+    /// Inputs which arrive out of order (or twice) are rejected straight away.
+    ///
+    pub async fn client_sequenced_input(
+        &mut self,
+        client_id: ClientId,
+        sequence: u32,
+        input: ArenaInput,
+    ) -> Result<(), Error> {
+        let client = self
+            .clients
+            .get_mut(&client_id)
+            .with_context(|| anyhow!("Client {} not found", client_id))?;
+
+        if client
+            .last_input_sequence
+            .is_some_and(|last_sequence| sequence <= last_sequence)
+        {
+            debug!(
+                "Rejecting out of order input {} from client {}",
+                sequence, client_id
+            );
+            return client
+                .tx
+                .send(MessageOut::InputRejected {
+                    sequence,
+                    reason: InputRejectedReason::OutOfOrder,
+                })
+                .await
+                .with_context(|| anyhow!("Failed to send InputRejected to client {}", client_id));
+        }
+        client.last_input_sequence = Some(sequence);
+
+        self.client_input(client_id, input, Some(sequence))
+    }
+
     pub fn client_part_arena(&mut self, client_id: ClientId) -> Result<(), Error> {
         let client = self
             .clients
@@ -184,8 +799,25 @@ impl Server {
         payload: MessageInPayload,
     ) -> Result<(), Error> {
         match payload {
-            MessageInPayload::Connect(ip_address, tx) => {
+            MessageInPayload::Connect(ip_address, mut tx) => {
                 info!("Client connected: {}", client_id);
+
+                // banned addresses are turned away before connecting, but may have been banned
+                // since; they're still added, so that they can be removed as usual once their
+                // connection has closed
+                if let Some(ban) = ip_address.and_then(|ip_address| self.bans.get(ip_address)) {
+                    info!(
+                        "Turning away client {} from banned {}",
+                        client_id, ban.ip_address
+                    );
+                    tx.send(MessageOut::Close {
+                        code: CLOSE_CODE_POLICY_VIOLATION,
+                        reason: format!("You are banned from this server: {}", ban.reason),
+                    })
+                    .await
+                    .with_context(|| anyhow!("Failed to send Close to client {}", client_id))?;
+                }
+
                 self.clients.insert(
                     client_id,
                     Client {
@@ -195,12 +827,20 @@ impl Server {
                         player: None,
                         arena: None,
                         updates_sent_so_far: 0,
+                        resend_state: false,
+                        desync_resent_at: None,
+                        last_input_sequence: None,
+                        latency_milliseconds: None,
                     },
                 );
             }
             MessageInPayload::Disconnect => {
                 info!("Client disconnected: {}", client_id);
 
+                self.matchmaking.remove(client_id);
+                self.client_stop_replay(client_id);
+                self.chat.remove_client(client_id);
+
                 self.client_part_arena(client_id).unwrap_or_else(|error| {
                     warn!("Failed to remove client from their arena: {}", error)
                 });
@@ -210,6 +850,27 @@ impl Server {
                     .ok_or_else(|| anyhow!("Failed to remove client {}", client_id))?;
             }
 
+            MessageInPayload::Latency(latency) => {
+                let client = self
+                    .clients
+                    .get_mut(&client_id)
+                    .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+                client.latency_milliseconds = Some(latency);
+
+                if let (Some(arena_id), Some(player_id)) = (client.arena, client.player)
+                    && let Some(arena) = self.arenas.get_mut(&arena_id)
+                {
+                    arena.set_player_ping(player_id, latency);
+                }
+            }
+
+            MessageInPayload::Shutdown { reason, timeout } => {
+                self.begin_shutdown(reason, timeout).await;
+            }
+            MessageInPayload::Query(query) => self.answer_query(query),
+            MessageInPayload::Admin(command) => self.handle_admin_command(command).await,
+
             MessageInPayload::GetArenaList => {
                 let arena_list: Vec<ArenaOverview> =
                     self.arenas.values().map(ArenaOverview::from).collect();
@@ -226,59 +887,548 @@ impl Server {
                     .with_context(|| anyhow!("Failed to send ArenaList to client {}", client_id))?;
             }
             MessageInPayload::Join { player, arena_id } => {
+                self.client_join_arena(client_id, player, arena_id).await?;
+            }
+            MessageInPayload::JoinByCode { player, arena_code } => {
+                let arena_id = self
+                    .find_arena_by_code(&arena_code)
+                    .ok_or_else(|| anyhow!("No arena with code {}", arena_code))?;
+                self.client_join_arena(client_id, player, Some(arena_id))
+                    .await?;
+            }
+            MessageInPayload::QuickMatch {
+                player,
+                preferences,
+            } => {
+                if !self.config.features.matchmaking {
+                    return Err(anyhow!("Quick match is not enabled on this server"));
+                }
+                // the queued player's name can become the name of the arena they're matched into
+                let player = player.normalized()?;
+
                 self.client_part_arena(client_id).unwrap_or_else(|error| {
                     warn!("Failed to remove client from their arena: {}", error)
                 });
 
-                let arena_id = match arena_id {
-                    Some(arena_id) => {
-                        if self.arenas.contains_key(&arena_id) {
-                            arena_id
-                        } else {
-                            self.new_arena(&player.name)
-                        }
+                let client = self
+                    .clients
+                    .get_mut(&client_id)
+                    .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+                client.player = None;
+                client.arena = None;
+
+                self.client_stop_replay(client_id);
+                self.matchmaking.enqueue(client_id, player, preferences);
+                info!("Client {} queued for a quick match", client_id);
+            }
+            MessageInPayload::LeaveQuickMatch => {
+                self.matchmaking
+                    .remove(client_id)
+                    .ok_or_else(|| anyhow!("Client {} not queued for a quick match", client_id))?;
+            }
+
+            MessageInPayload::Chat { text } => {
+                if !self.config.features.chat {
+                    return Err(anyhow!("Chat is not enabled on this server"));
+                }
+
+                let client = self
+                    .clients
+                    .get(&client_id)
+                    .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
+
+                let arena_id = client
+                    .arena
+                    .with_context(|| anyhow!("Client {} not in an arena", client_id))?;
+
+                let player_id = client
+                    .player
+                    .with_context(|| anyhow!("Client {} has no player", client_id))?;
+
+                let message = self
+                    .chat
+                    .post(client_id, arena_id, player_id, &text)
+                    .with_context(|| anyhow!("Failed to post chat message"))?;
+
+                for client in self
+                    .clients
+                    .values_mut()
+                    .filter(|client| client.arena == Some(arena_id))
+                {
+                    if let Err(error) = client
+                        .tx
+                        .send(MessageOut::ChatMessage(message.clone()))
+                        .await
+                    {
+                        error!("Failed to send ChatMessage to client: {}", error);
                     }
-                    None => self.new_arena(&player.name),
-                };
+                }
+            }
 
-                let arena = self
-                    .arenas
-                    .get_mut(&arena_id)
-                    .ok_or_else(|| anyhow!("Arena {} not found", arena_id))?;
+            MessageInPayload::WatchReplay { replay_id } => {
+                self.client_watch_replay(client_id, replay_id).await?;
+            }
+            MessageInPayload::ReplayLoaded(replay_id, replay) => {
+                self.client_replay_loaded(client_id, replay_id, *replay)
+                    .await?;
+            }
+            MessageInPayload::ReplayControl(control) => {
+                self.client_control_replay(client_id, control).await?;
+            }
 
-                if arena.players.len() >= arena.max_players {
-                    return Err(anyhow!("Arena {} is full", arena_id));
+            MessageInPayload::Start => {
+                if self.shutdown.is_some() {
+                    return Err(anyhow!("Server is shutting down"));
                 }
 
-                let player_id = player.id;
+                self.client_input(client_id, ArenaInput::Start, None)?;
+            }
+            MessageInPayload::Turn(Turn::Direction(direction)) => {
+                self.client_input(client_id, ArenaInput::Turn(direction), None)?;
+            }
+            MessageInPayload::Turn(Turn::Sequenced {
+                direction,
+                sequence,
+                tick,
+            }) => {
+                self.client_sequenced_input(
+                    client_id,
+                    sequence,
+                    ArenaInput::TurnAt(direction, tick),
+                )
+                .await?;
+            }
 
+            MessageInPayload::DesyncDetected => {
                 let client = self
                     .clients
                     .get_mut(&client_id)
                     .ok_or_else(|| anyhow!("Client {} not found", client_id))?;
 
-                arena.add_player(player);
-                client.player = Some(player_id);
-                client.arena = Some(arena_id);
+                let now = Instant::now();
+                if client.desync_resent_at.is_some_and(|resent_at| {
+                    now.duration_since(resent_at) < Duration::from_secs(DESYNC_RESEND_MIN_SECONDS)
+                }) {
+                    debug!(
+                        "Client {} reported another desync too soon, ignoring it",
+                        client_id
+                    );
+                    return Ok(());
+                }
 
-                client
-                    .tx
-                    .send(MessageOut::ArenaJoined(arena.id, player_id))
-                    .await
-                    .with_context(|| {
-                        anyhow!("Failed to send ArenaJoined to client {}", client_id)
-                    })?;
+                if let Some(playback) = self.replay_playbacks.get(&client_id) {
+                    warn!(
+                        "Client {} reported a desync while watching a replay, resending state",
+                        client_id
+                    );
+                    client
+                        .tx
+                        .send(MessageOut::ArenaState(Box::from(playback.state().clone())))
+                        .await
+                        .with_context(|| {
+                            anyhow!("Failed to send ArenaState to client {}", client_id)
+                        })?;
+                    client.desync_resent_at = Some(now);
+                    return Ok(());
+                }
+
+                let arena_id = client
+                    .arena
+                    .with_context(|| anyhow!("Client {} not in an arena", client_id))?;
 
-                info!("Player {} joined arena {}", player_id, arena_id);
+                warn!(
+                    "Client {} reported a desync in arena {}, resending state",
+                    client_id, arena_id
+                );
+                // send_updates will resend the whole ArenaState on the next send
+                client.resend_state = true;
+                client.desync_resent_at = Some(now);
             }
+        }
+        Ok(())
+    }
 
-            MessageInPayload::Start => {
-                self.client_input(client_id, ArenaInput::Start)?;
+    ///
+    /// Answers a query from the web server.
+    /// Nobody may be waiting for the answer any more (if the query timed out), which is fine.
+    ///
+    pub fn answer_query(&self, query: ServerQuery) {
+        match query {
+            ServerQuery::ArenaList(tx) => {
+                let _ = tx.send(self.arenas.values().map(ArenaOverview::from).collect());
             }
-            MessageInPayload::Turn(direction) => {
-                self.client_input(client_id, ArenaInput::Turn(direction))?;
+            ServerQuery::Arena(arena_id, tx) => {
+                let _ = tx.send(self.arenas.get(&arena_id).map(ArenaOverview::from));
+            }
+            ServerQuery::Status(tx) => {
+                let _ = tx.send(ServerStatus {
+                    version: env!("CARGO_PKG_VERSION"),
+                    uptime_seconds: self.started_at.elapsed().as_secs(),
+                    tick_rate: self.config.tick_rate,
+                    send_rate: self.config.send_rate,
+                    clients: self.clients.len(),
+                    players: self.arenas.values().map(|arena| arena.players.len()).sum(),
+                    arenas: self.arenas.len(),
+                    arenas_started: self
+                        .arenas
+                        .values()
+                        .filter(|arena| arena.started.is_some())
+                        .count(),
+                    quick_match_queued: self.matchmaking.len(),
+                    shutting_down: self.shutdown.is_some(),
+                });
             }
         }
+    }
+}
+
+impl Server {
+    ///
+    /// Carries out a command from the admin api, and replies with the outcome.
+    /// As with queries, nobody may be waiting for the reply any more.
+    ///
+    pub async fn handle_admin_command(&mut self, command: AdminCommand) {
+        match command {
+            AdminCommand::ListClients(tx) => {
+                let _ = tx.send(Ok(self.client_overviews()));
+            }
+            AdminCommand::Kick {
+                client_id,
+                reason,
+                tx,
+            } => {
+                let _ = tx.send(self.kick_client(client_id, reason).await);
+            }
+            AdminCommand::Ban {
+                client_id,
+                reason,
+                duration,
+                tx,
+            } => {
+                let _ = tx.send(self.ban_client(client_id, reason, duration).await);
+            }
+            AdminCommand::BanIpAddress {
+                ip_address,
+                reason,
+                duration,
+                tx,
+            } => {
+                let result = if self.bans.is_configured(ip_address) {
+                    Err(AdminError::Invalid(format!(
+                        "{} is already banned in the server's configuration",
+                        ip_address
+                    )))
+                } else {
+                    self.ban_ip_address(ip_address, reason, duration).await
+                };
+                let _ = tx.send(result);
+            }
+            AdminCommand::ListBans(tx) => {
+                let _ = tx.send(Ok(self.bans.list()));
+            }
+            AdminCommand::Unban { ip_address, tx } => {
+                let result = if self.bans.is_configured(ip_address) {
+                    Err(AdminError::Invalid(format!(
+                        "{} is banned in the server's configuration",
+                        ip_address
+                    )))
+                } else if self.bans.unban(ip_address) {
+                    info!("Unbanned {}", ip_address);
+                    Ok(())
+                } else {
+                    Err(AdminError::NotFound(format!(
+                        "{} is not banned",
+                        ip_address
+                    )))
+                };
+                let _ = tx.send(result);
+            }
+            AdminCommand::CloseArena {
+                arena_id,
+                reason,
+                tx,
+            } => {
+                let _ = tx.send(self.close_arena(arena_id, reason).await);
+            }
+            AdminCommand::Announce { text, tx } => {
+                let _ = tx.send(self.announce(text).await);
+            }
+            AdminCommand::GetSettings(tx) => {
+                let _ = tx.send(Ok(self.runtime_settings()));
+            }
+            AdminCommand::SetSettings { settings, tx } => {
+                let result = settings.validate().map(|()| {
+                    info!("Changing settings to {:?}", settings);
+                    self.max_arenas = settings.max_arenas;
+                    self.runtime_settings()
+                });
+                let _ = tx.send(result);
+            }
+        }
+    }
+
+    fn client_overviews(&self) -> Vec<ClientOverview> {
+        self.clients
+            .values()
+            .map(|client| ClientOverview {
+                id: client.id,
+                ip_address: client.ip_address,
+                arena: client.arena,
+                player: client
+                    .arena
+                    .zip(client.player)
+                    .and_then(|(arena_id, player_id)| {
+                        self.arenas.get(&arena_id)?.players.get(&player_id).cloned()
+                    }),
+                latency_milliseconds: client.latency_milliseconds,
+            })
+            .collect()
+    }
+
+    fn runtime_settings(&self) -> RuntimeSettings {
+        RuntimeSettings {
+            max_arenas: self.max_arenas,
+        }
+    }
+
+    ///
+    /// Closes the client's connection. The client is removed once the connection has closed.
+    ///
+    async fn kick_client(&mut self, client_id: ClientId, reason: String) -> Result<(), AdminError> {
+        let client = self
+            .clients
+            .get_mut(&client_id)
+            .ok_or_else(|| AdminError::NotFound(format!("Client {} not found", client_id)))?;
+
+        info!("Kicking client {}: {}", client_id, reason);
+        if let Err(error) = client
+            .tx
+            .send(MessageOut::Close {
+                code: CLOSE_CODE_POLICY_VIOLATION,
+                reason,
+            })
+            .await
+        {
+            error!("Failed to send Close to client: {}", error);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Bans the client's ip address, and kicks every client connected from it.
+    ///
+    async fn ban_client(
+        &mut self,
+        client_id: ClientId,
+        reason: String,
+        duration: Option<Duration>,
+    ) -> Result<Ban, AdminError> {
+        let ip_address = self
+            .clients
+            .get(&client_id)
+            .ok_or_else(|| AdminError::NotFound(format!("Client {} not found", client_id)))?
+            .ip_address
+            .ok_or_else(|| {
+                AdminError::Invalid(format!("Client {} has no known ip address", client_id))
+            })?;
+
+        self.ban_ip_address(ip_address, reason, duration).await
+    }
+
+    ///
+    /// Bans the ip address, and kicks every client connected from it.
+    ///
+    async fn ban_ip_address(
+        &mut self,
+        ip_address: IpAddr,
+        reason: String,
+        duration: Option<Duration>,
+    ) -> Result<Ban, AdminError> {
+        info!("Banning {} for {:?}: {}", ip_address, duration, reason);
+        let ban = self.bans.ban(ip_address, reason.clone(), duration);
+
+        let banned_clients: Vec<ClientId> = self
+            .clients
+            .values()
+            .filter(|client| client.ip_address == Some(ip_address))
+            .map(|client| client.id)
+            .collect();
+        for client_id in banned_clients {
+            self.kick_client(
+                client_id,
+                format!("You are banned from this server: {}", reason),
+            )
+            .await?;
+        }
+
+        Ok(ban)
+    }
+
+    ///
+    /// Disconnects everybody in the arena, and removes it.
+    ///
+    async fn close_arena(&mut self, arena_id: ArenaId, reason: String) -> Result<(), AdminError> {
+        if self.arenas.remove(&arena_id).is_none() {
+            return Err(AdminError::NotFound(format!(
+                "Arena {} not found",
+                arena_id
+            )));
+        }
+        self.chat.remove_arena(arena_id);
+        self.replay_recorder.remove_arena(arena_id);
+
+        info!("Closing arena {}: {}", arena_id, reason);
+        for client in self
+            .clients
+            .values_mut()
+            .filter(|client| client.arena == Some(arena_id))
+        {
+            client.arena = None;
+            client.player = None;
+            client.updates_sent_so_far = 0;
+
+            if let Err(error) = client
+                .tx
+                .send(MessageOut::Close {
+                    code: CLOSE_CODE_NORMAL,
+                    reason: reason.clone(),
+                })
+                .await
+            {
+                error!("Failed to send Close to client: {}", error);
+            }
+        }
+
         Ok(())
     }
+
+    /// Sends an announcement to every client, returning how many it was sent to.
+    async fn announce(&mut self, text: String) -> Result<usize, AdminError> {
+        let text = text.trim().to_owned();
+        if text.is_empty() {
+            return Err(AdminError::Invalid(
+                "Announcements must not be empty".to_owned(),
+            ));
+        }
+        info!("Announcing: {}", text);
+
+        let mut sent = 0;
+        for client in self.clients.values_mut() {
+            match client
+                .tx
+                .send(MessageOut::Announcement { text: text.clone() })
+                .await
+            {
+                Ok(()) => sent += 1,
+                Err(error) => error!("Failed to send Announcement to client: {}", error),
+            }
+        }
+
+        Ok(sent)
+    }
+}
+
+impl Server {
+    ///
+    /// Warns every client that the server is shutting down, and stops new arenas and rounds
+    /// from being started. Asking again can only bring the shutdown forward.
+    ///
+    pub async fn begin_shutdown(&mut self, reason: String, timeout: Duration) {
+        let now = Instant::now();
+        let deadline = now + timeout;
+        let deadline = match &mut self.shutdown {
+            Some(shutdown) => {
+                shutdown.deadline = shutdown.deadline.min(deadline);
+                shutdown.deadline
+            }
+            None => {
+                info!(
+                    "Shutting down within {} seconds: {}",
+                    timeout.as_secs(),
+                    reason
+                );
+                self.shutdown = Some(ShutdownProgress {
+                    reason: reason.clone(),
+                    deadline,
+                    clients_closed_at: None,
+                });
+                deadline
+            }
+        };
+
+        for client in self.clients.values_mut() {
+            if let Err(error) = client
+                .tx
+                .send(MessageOut::ServerShutdown {
+                    in_seconds: deadline.saturating_duration_since(now).as_secs(),
+                    reason: reason.clone(),
+                })
+                .await
+            {
+                error!("Failed to send ServerShutdown to client: {}", error);
+            }
+        }
+    }
+
+    ///
+    /// Moves a shutdown along: once every round in progress has finished (or the timeout has
+    /// passed) and every replay has been saved, the clients' websockets are closed.
+    ///
+    /// Returns true once the server is ready to stop.
+    ///
+    pub async fn process_shutdown(&mut self) -> bool {
+        let now = Instant::now();
+        let shutdown = match &self.shutdown {
+            Some(shutdown) => shutdown,
+            None => return false,
+        };
+
+        if let Some(clients_closed_at) = shutdown.clients_closed_at {
+            return self.clients.is_empty()
+                || now.duration_since(clients_closed_at)
+                    >= Duration::from_secs(SHUTDOWN_CLOSE_GRACE_SECONDS);
+        }
+
+        // rounds with a winner are only waiting for the winner to crash, so aren't waited for
+        let rounds_in_progress = self
+            .arenas
+            .values()
+            .filter(|arena| arena.started.is_some() && arena.winner.is_none())
+            .count();
+        if rounds_in_progress > 0 {
+            if now < shutdown.deadline {
+                return false;
+            }
+            warn!(
+                "Shutdown timeout reached with {} rounds still in progress",
+                rounds_in_progress
+            );
+        }
+
+        if self.replay_saves_in_progress.load(Ordering::SeqCst) > 0 {
+            return false;
+        }
+
+        let reason = shutdown.reason.clone();
+        for client in self.clients.values_mut() {
+            if let Err(error) = client
+                .tx
+                .send(MessageOut::Close {
+                    code: CLOSE_CODE_GOING_AWAY,
+                    reason: reason.clone(),
+                })
+                .await
+            {
+                error!("Failed to close client websocket: {}", error);
+            }
+        }
+
+        if let Some(shutdown) = &mut self.shutdown {
+            shutdown.clients_closed_at = Some(now);
+        }
+
+        false
+    }
 }
@@ -0,0 +1,103 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use crate::server::{ArenaId, Ban, ClientId, Player};
+
+pub type AdminReply<T> = oneshot::Sender<Result<T, AdminError>>;
+
+///
+/// Commands from the admin API, answered on the enclosed channel.
+///
+#[derive(Debug)]
+pub enum AdminCommand {
+    ListClients(AdminReply<Vec<ClientOverview>>),
+    Kick {
+        client_id: ClientId,
+        reason: String,
+        tx: AdminReply<()>,
+    },
+    /// Kicks the client, and turns away connections from their ip address
+    /// until the ban expires (or the server restarts, if it has no duration).
+    Ban {
+        client_id: ClientId,
+        reason: String,
+        duration: Option<Duration>,
+        tx: AdminReply<Ban>,
+    },
+    /// Turns away connections from the ip address (kicking any clients connected from it)
+    /// until the ban expires (or the server restarts, if it has no duration).
+    BanIpAddress {
+        ip_address: IpAddr,
+        reason: String,
+        duration: Option<Duration>,
+        tx: AdminReply<Ban>,
+    },
+    ListBans(AdminReply<Vec<Ban>>),
+    Unban {
+        ip_address: IpAddr,
+        tx: AdminReply<()>,
+    },
+    /// Disconnects everybody in the arena, and removes it.
+    CloseArena {
+        arena_id: ArenaId,
+        reason: String,
+        tx: AdminReply<()>,
+    },
+    /// Shows a message to every connected client, replying with how many were sent it.
+    Announce {
+        text: String,
+        tx: AdminReply<usize>,
+    },
+    GetSettings(AdminReply<RuntimeSettings>),
+    SetSettings {
+        settings: RuntimeSettings,
+        tx: AdminReply<RuntimeSettings>,
+    },
+}
+
+#[derive(Debug)]
+pub enum AdminError {
+    NotFound(String),
+    Invalid(String),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::NotFound(message) | AdminError::Invalid(message) => message.fmt(f),
+        }
+    }
+}
+
+/// A connected client, as shown to admins.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientOverview {
+    pub id: ClientId,
+    pub ip_address: Option<IpAddr>,
+    pub arena: Option<ArenaId>,
+    pub player: Option<Player>,
+    pub latency_milliseconds: Option<u64>,
+}
+
+/// Settings which can be changed while the server is running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeSettings {
+    /// How many arenas can be open at once (unlimited if unset).
+    pub max_arenas: Option<usize>,
+}
+
+impl RuntimeSettings {
+    pub fn validate(&self) -> Result<(), AdminError> {
+        if self.max_arenas == Some(0) {
+            return Err(AdminError::Invalid(
+                "max_arenas must be at least 1".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
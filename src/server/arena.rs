@@ -1,13 +1,15 @@
+mod checksum;
 mod entities;
 mod input;
 mod updates;
 mod util;
 
 use chrono::{DateTime, Duration as OldDuration, Utc};
+use indexmap::IndexMap;
 use log::{error, trace};
 use rand_core::{OsRng, RngCore};
-use serde_derive::Serialize;
-use std::collections::HashMap;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::mem;
 
 pub use self::entities::*;
@@ -15,19 +17,39 @@ pub use self::input::*;
 pub use self::updates::*;
 pub use self::util::*;
 
-use crate::server::{ArenaId, ArenaLine, ArenaPoint, Direction, Player, PlayerId};
+use crate::server::{
+    ArenaId, ArenaLine, ArenaPoint, Direction, PLAYER_NAME_MAX_LENGTH, Player, PlayerColor,
+    PlayerId,
+};
 
 const ARENA_WIDTH: f64 = 560.0;
 const ARENA_HEIGHT: f64 = 560.0;
-const ARENA_MAX_PLAYERS: usize = 8;
+pub const ARENA_MAX_PLAYERS: usize = 8;
 const ARENA_START_TIMER_SECONDS: i64 = 1;
+const ARENA_CODE_LENGTH: usize = 5;
+// omits I, L and O, which are easily mistaken for each other (or for 1 and 0)
+const ARENA_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ";
 const LIGHTCYCLE_SPEED: f64 = 55.0;
+/// How often (in ticks) a checksum of the arena is added to its updates.
+const ARENA_CHECKSUM_INTERVAL_TICKS: u32 = 20;
+/// How many turns can be waiting in a player's input queue, by default.
+pub const ARENA_DEFAULT_MAX_QUEUED_TURNS: usize = 3;
+/// How far back (in ticks) a turn can be placed to make up for the turning client's latency.
+pub const ARENA_LAG_COMPENSATION_MAX_TICKS: u64 = 6;
 // const LIGHTCYCLE_BRAKE_SPEED: f64 = 40.0;
 // const LIGHTCYCLE_BOOST_SPEED: f64 = 70.0;
 
-#[derive(Debug, Clone, Serialize)]
+///
+/// Entities are kept in the order they were added to the arena (so players in join order),
+/// which keeps the order of the updates produced each tick the same from run to run.
+/// Serialized, an EntityMap is still a json object keyed by PlayerId.
+///
+pub type EntityMap<T> = IndexMap<PlayerId, T>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Arena {
     pub id: ArenaId,
+    pub code: String,
     pub name: String,
     pub width: f64,
     pub height: f64,
@@ -35,15 +57,58 @@ pub struct Arena {
 
     pub started: Option<DateTime<Utc>>,
     pub winner: Option<PlayerId>,
+    /// The number of ticks since the round started.
+    #[serde(default)]
+    pub tick: u64,
 
-    pub players: HashMap<PlayerId, Player>,
-    pub lightcycles: HashMap<PlayerId, Lightcycle>,
-    pub lightribbons: HashMap<PlayerId, Lightribbon>,
+    pub players: EntityMap<Player>,
+    pub lightcycles: EntityMap<Lightcycle>,
+    pub lightribbons: EntityMap<Lightribbon>,
+    /// The round trip time (in milliseconds) of each player's connection.
+    #[serde(default)]
+    pub pings: EntityMap<u64>,
 
     #[serde(skip)]
     pub updates: Vec<ArenaUpdate>,
     #[serde(skip)]
     updates_applied_so_far: usize,
+    #[serde(skip)]
+    ticks_since_checksum: u32,
+    #[serde(skip)]
+    lightcycle_trails: EntityMap<LightcycleTrail>,
+    #[serde(skip)]
+    input_queues: EntityMap<VecDeque<QueuedInput>>,
+    #[serde(skip, default = "default_max_queued_turns")]
+    max_queued_turns: usize,
+    /// The turns taken from the input queues on the latest tick, in the order they were made.
+    #[serde(skip)]
+    pub turns_processed: Vec<(PlayerId, ArenaInput)>,
+    #[serde(skip)]
+    processed_inputs: Vec<ProcessedInput>,
+    /// The length of the latest tick, in seconds.
+    #[serde(skip)]
+    tick_seconds: f64,
+}
+
+fn default_max_queued_turns() -> usize {
+    ARENA_DEFAULT_MAX_QUEUED_TURNS
+}
+
+/// An input waiting in a player's queue, with the client's sequence number if it sent one.
+#[derive(Debug, Copy, Clone)]
+struct QueuedInput {
+    input: ArenaInput,
+    sequence: Option<u32>,
+}
+
+///
+/// The positions a lightcycle was at after each of the last few ticks,
+/// since it last changed direction. Used to place lag compensated turns.
+///
+#[derive(Debug, Clone, Default)]
+struct LightcycleTrail {
+    direction: Option<Direction>,
+    positions: VecDeque<ArenaPoint>,
 }
 
 impl Arena {
@@ -54,10 +119,70 @@ impl Arena {
         }
     }
 
+    pub fn set_max_players(&mut self, max_players: usize) {
+        self.max_players = max_players.clamp(1, ARENA_MAX_PLAYERS);
+    }
+
+    pub fn set_max_queued_turns(&mut self, max_queued_turns: usize) {
+        self.max_queued_turns = max_queued_turns.max(1);
+    }
+
+    /// The length of the latest tick, in seconds.
+    pub fn tick_seconds(&self) -> f64 {
+        self.tick_seconds
+    }
+
+    ///
+    /// Returns the player with their name and color changed as needed
+    /// to keep them distinguishable from the other players in this arena.
+    ///
+    /// Duplicate names get a numbered suffix, and colors too similar to another player's
+    /// are swapped for the nearest named color nobody is using (if there is one left).
+    ///
+    pub fn resolve_player_conflicts(&self, mut player: Player) -> Player {
+        let others: Vec<&Player> = self
+            .players
+            .values()
+            .filter(|other| other.id != player.id)
+            .collect();
+
+        if others.iter().any(|other| other.name == player.name) {
+            let name = player.name.clone();
+            player.name = (2..)
+                .map(|suffix| {
+                    let suffix = format!("_{}", suffix);
+                    let base: String = name
+                        .chars()
+                        .take(PLAYER_NAME_MAX_LENGTH.saturating_sub(suffix.len()))
+                        .collect();
+                    format!("{}{}", base, suffix)
+                })
+                .find(|candidate| others.iter().all(|other| other.name != *candidate))
+                .expect("Ran out of name suffixes");
+        }
+
+        let used_colors: Vec<PlayerColor> = others.iter().map(|other| other.color).collect();
+        if used_colors.iter().any(|used| used.is_similar(player.color))
+            && let Some(color) = player.color.nearest_free(&used_colors)
+        {
+            player.color = color;
+        }
+
+        player
+    }
+
     pub fn add_player(&mut self, player: Player) {
         self.updates.push(ArenaUpdate::AddPlayer(player.id, player));
     }
 
+    pub fn set_player_ping(&mut self, player_id: PlayerId, ping_milliseconds: u64) {
+        if self.pings.get(&player_id) == Some(&ping_milliseconds) {
+            return;
+        }
+        self.updates
+            .push(ArenaUpdate::UpdatePlayerPing(player_id, ping_milliseconds));
+    }
+
     pub fn remove_player(&mut self, player_id: PlayerId) {
         self.updates.push(ArenaUpdate::RemovePlayer(player_id));
     }
@@ -79,26 +204,134 @@ impl Arena {
     }
 
     pub fn process_input(&mut self, player_id: PlayerId, input_event: ArenaInput) {
-        input_event
-            .process_into_updates(self, player_id)
-            .drain(..)
-            .for_each(|update| self.updates.push(update));
+        self.process_input_at(Utc::now(), player_id, input_event)
+    }
+
+    ///
+    /// Processes an input as though it were received at `now`.
+    ///
+    /// Turns are queued, and made one per tick (per player) so that turns made in quick
+    /// succession each get a segment of their own. Other inputs are processed right away.
+    ///
+    pub fn process_input_at(
+        &mut self,
+        now: DateTime<Utc>,
+        player_id: PlayerId,
+        input_event: ArenaInput,
+    ) {
+        self.process_sequenced_input_at(now, player_id, input_event, None)
+    }
+
+    ///
+    /// Processes an input as though it were received at `now`, like process_input_at.
+    ///
+    /// Inputs tagged with a client's sequence number are reported by take_processed_inputs
+    /// once they've been made (or dropped), so that the client can be told what became of them.
+    ///
+    pub fn process_sequenced_input_at(
+        &mut self,
+        now: DateTime<Utc>,
+        player_id: PlayerId,
+        input_event: ArenaInput,
+        sequence: Option<u32>,
+    ) {
+        if let ArenaInput::Turn(_) | ArenaInput::TurnAt(_, _) = input_event {
+            let queue = self.input_queues.entry(player_id).or_default();
+            if queue.len() < self.max_queued_turns {
+                queue.push_back(QueuedInput {
+                    input: input_event,
+                    sequence,
+                });
+                return;
+            }
+
+            trace!(
+                "Dropping turn for player {}: input queue is full",
+                player_id
+            );
+            self.report_input(player_id, sequence, InputOutcome::Dropped);
+            return;
+        }
+
+        let updates = input_event.process_into_updates(self, player_id, now);
+        let outcome = self.outcome_of(&updates);
+        self.updates.extend(updates);
+        self.report_input(player_id, sequence, outcome);
+    }
+
+    ///
+    /// Takes the outcomes of the sequenced inputs made (or dropped) since this was last called.
+    ///
+    pub fn take_processed_inputs(&mut self) -> Vec<ProcessedInput> {
+        mem::take(&mut self.processed_inputs)
+    }
+
+    // inputs which produced no updates were refused
+    fn outcome_of(&self, updates: &[ArenaUpdate]) -> InputOutcome {
+        match updates.is_empty() {
+            // the updates are applied on the next tick
+            false => InputOutcome::Applied(self.tick + 1),
+            true => InputOutcome::Dropped,
+        }
+    }
+
+    fn report_input(&mut self, player_id: PlayerId, sequence: Option<u32>, outcome: InputOutcome) {
+        if let Some(sequence) = sequence {
+            self.processed_inputs.push(ProcessedInput {
+                player_id,
+                sequence,
+                outcome,
+            });
+        }
+    }
+
+    fn drop_queued_inputs(&mut self) {
+        for (player_id, queue) in mem::take(&mut self.input_queues) {
+            for queued in queue {
+                self.report_input(player_id, queued.sequence, InputOutcome::Dropped);
+            }
+        }
     }
 
     pub fn update(&mut self, delta_time: f64) {
+        self.update_at(Utc::now(), delta_time)
+    }
+
+    ///
+    /// Steps the arena forward as though the current time were `now`.
+    /// Used to run arenas faster (or slower) than real time.
+    ///
+    pub fn update_at(&mut self, now: DateTime<Utc>, delta_time: f64) {
         // apply process_input updates
         self.apply_updates();
+        self.turns_processed.clear();
+        self.tick_seconds = delta_time;
 
         let started = match self.started {
             Some(started) => started,
-            None => return,
+            None => {
+                self.drop_queued_inputs();
+                return;
+            }
         };
 
-        let now = Utc::now();
+        // only once the round has started, so that idle lobbies don't pile up checksums
+        self.ticks_since_checksum += 1;
+        if self.ticks_since_checksum >= ARENA_CHECKSUM_INTERVAL_TICKS {
+            self.ticks_since_checksum = 0;
+            self.updates.push(ArenaUpdate::Checksum(self.checksum()));
+            self.apply_updates();
+        }
+
+        // turns can only be made while the round is running
         if now < started {
+            self.drop_queued_inputs();
             return;
         }
 
+        self.process_queued_turns(now).apply_updates();
+
+        self.tick += 1;
         self.update_lightcycle_positions(delta_time)
             .apply_updates()
             .calculate_lightcycle_collisions(delta_time)
@@ -108,13 +341,32 @@ impl Arena {
             .test_win_condition()
             .apply_updates()
             .test_round_end()
-            .apply_updates();
+            .apply_updates()
+            .record_lightcycle_trails();
     }
 
     //
     // update helpers
     //
 
+    fn process_queued_turns(&mut self, now: DateTime<Utc>) -> &mut Self {
+        let turns: Vec<(PlayerId, QueuedInput)> = self
+            .input_queues
+            .iter_mut()
+            .filter_map(|(id, queue)| Some((*id, queue.pop_front()?)))
+            .collect();
+        self.input_queues.retain(|_, queue| !queue.is_empty());
+
+        for (player_id, turn) in turns {
+            let updates = turn.input.process_into_updates(self, player_id, now);
+            let outcome = self.outcome_of(&updates);
+            self.updates.extend(updates);
+            self.turns_processed.push((player_id, turn.input));
+            self.report_input(player_id, turn.sequence, outcome);
+        }
+        self
+    }
+
     fn update_lightcycle_positions(&mut self, delta_time: f64) -> &mut Self {
         for (id, lightcycle) in self.lightcycles.iter() {
             if lightcycle.dead {
@@ -230,7 +482,7 @@ impl Arena {
             return self;
         }
 
-        if self.lightcycles.iter().count() <= 1 {
+        if self.lightcycles.len() <= 1 {
             return self;
         }
 
@@ -246,6 +498,32 @@ impl Arena {
         self
     }
 
+    fn record_lightcycle_trails(&mut self) -> &mut Self {
+        if self.tick <= 1 {
+            self.lightcycle_trails.clear();
+        }
+        self.lightcycle_trails
+            .retain(|id, _| self.lightcycles.contains_key(id));
+
+        for (id, lightcycle) in self.lightcycles.iter() {
+            if lightcycle.dead {
+                continue;
+            }
+
+            let trail = self.lightcycle_trails.entry(*id).or_default();
+            if trail.direction != Some(lightcycle.direction) {
+                trail.direction = Some(lightcycle.direction);
+                trail.positions.clear();
+            }
+
+            trail.positions.push_back(lightcycle.position);
+            if trail.positions.len() as u64 > ARENA_LAG_COMPENSATION_MAX_TICKS + 1 {
+                trail.positions.pop_front();
+            }
+        }
+        self
+    }
+
     fn test_round_end(&mut self) -> &mut Self {
         if self.lightcycles.values().all(|lightcycle| lightcycle.dead) {
             self.updates.push(ArenaUpdate::End);
@@ -258,6 +536,7 @@ impl Default for Arena {
     fn default() -> Self {
         Self {
             id: Default::default(),
+            code: generate_arena_code(),
             name: Default::default(),
             width: ARENA_WIDTH,
             height: ARENA_HEIGHT,
@@ -265,13 +544,22 @@ impl Default for Arena {
 
             started: None,
             winner: None,
+            tick: 0,
 
             players: Default::default(),
             lightcycles: Default::default(),
             lightribbons: Default::default(),
+            pings: Default::default(),
 
             updates: Default::default(),
             updates_applied_so_far: 0,
+            ticks_since_checksum: 0,
+            lightcycle_trails: Default::default(),
+            input_queues: Default::default(),
+            max_queued_turns: ARENA_DEFAULT_MAX_QUEUED_TURNS,
+            turns_processed: Default::default(),
+            processed_inputs: Default::default(),
+            tick_seconds: 0.0,
         }
     }
 }
@@ -280,16 +568,18 @@ impl Default for Arena {
 #[derive(Debug, Clone, Serialize)]
 pub struct ArenaOverview {
     id: ArenaId,
+    code: String,
     name: String,
     max_players: usize,
     started: Option<DateTime<Utc>>,
-    players: HashMap<PlayerId, Player>,
+    players: EntityMap<Player>,
 }
 
 impl From<&Arena> for ArenaOverview {
     fn from(arena: &Arena) -> Self {
         Self {
             id: arena.id,
+            code: arena.code.clone(),
             name: arena.name.clone(),
             max_players: arena.max_players,
             started: arena.started,
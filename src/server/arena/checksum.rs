@@ -0,0 +1,74 @@
+use super::*;
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+impl Arena {
+    ///
+    /// Returns a hash of the parts of the arena which clients rebuild from updates,
+    /// so that clients can check that their copy of the arena hasn't drifted.
+    ///
+    /// Clients compute the same hash (see `client/src/utils/arenaChecksum.ts`),
+    /// so the canonical form must be kept in step with that implementation:
+    /// entities sorted by id, and coordinates quantized to hundredths.
+    ///
+    pub fn checksum(&self) -> u32 {
+        let mut canonical = String::new();
+
+        for (id, player) in sorted_by_id(&self.players) {
+            canonical.push_str(&format!("p{}:{}:{};", id, player.name, player.color));
+        }
+        for (id, lightcycle) in sorted_by_id(&self.lightcycles) {
+            canonical.push_str(&format!(
+                "c{}:{},{}:{}:{};",
+                id,
+                quantize(lightcycle.position.x),
+                quantize(lightcycle.position.y),
+                direction_name(lightcycle.direction),
+                lightcycle.dead as u8
+            ));
+        }
+        for (id, lightribbon) in sorted_by_id(&self.lightribbons) {
+            let points: Vec<String> = lightribbon
+                .points
+                .iter()
+                .map(|point| format!("{},{}", quantize(point.x), quantize(point.y)))
+                .collect();
+            canonical.push_str(&format!("r{}:{};", id, points.join(" ")));
+        }
+        if let Some(winner) = self.winner {
+            canonical.push_str(&format!("w{}", winner));
+        }
+
+        fnv1a(canonical.as_bytes())
+    }
+}
+
+fn sorted_by_id<T>(entities: &EntityMap<T>) -> Vec<(String, &T)> {
+    let mut sorted: Vec<(String, &T)> = entities
+        .iter()
+        .map(|(id, entity)| (id.to_string(), entity))
+        .collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    sorted
+}
+
+// matches javascript's Math.round, which rounds halves towards positive infinity
+fn quantize(value: f64) -> i64 {
+    (value * 100.0 + 0.5).floor() as i64
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Left => "left",
+        Direction::Right => "right",
+        Direction::Down => "down",
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(FNV_PRIME)
+    })
+}
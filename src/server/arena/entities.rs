@@ -1,6 +1,6 @@
 use super::*;
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Lightcycle {
     pub position: ArenaPoint,
     pub direction: Direction,
@@ -19,7 +19,7 @@ impl Default for Lightcycle {
     }
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Lightribbon {
     pub points: Vec<ArenaPoint>,
 }
@@ -1,13 +1,41 @@
 use super::*;
 
-#[derive(Debug, Clone, Hash, PartialEq)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Serialize, Deserialize)]
 pub enum ArenaInput {
     Start,
     Turn(Direction),
+    /// A turn made by a client while it was showing the given arena tick.
+    TurnAt(Direction, u64),
+}
+
+/// What became of an input a client tagged with a sequence number.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputOutcome {
+    /// The input was made, and its updates applied on the given tick.
+    Applied(u64),
+    /// The input was refused, or dropped before it could be made.
+    Dropped,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProcessedInput {
+    pub player_id: PlayerId,
+    pub sequence: u32,
+    pub outcome: InputOutcome,
 }
 
 impl ArenaInput {
-    pub fn process_into_updates(self, arena: &Arena, player_id: PlayerId) -> Vec<ArenaUpdate> {
+    pub fn process_into_updates(
+        self,
+        arena: &Arena,
+        player_id: PlayerId,
+        now: DateTime<Utc>,
+    ) -> Vec<ArenaUpdate> {
+        let client_tick = match self {
+            ArenaInput::TurnAt(_, client_tick) => Some(client_tick),
+            _ => None,
+        };
+
         match self {
             ArenaInput::Start => {
                 if arena.started.is_some() {
@@ -65,13 +93,13 @@ impl ArenaInput {
 
                 // begin countdown
                 updates.push(ArenaUpdate::Start(
-                    Utc::now() + OldDuration::seconds(ARENA_START_TIMER_SECONDS),
+                    now + OldDuration::seconds(ARENA_START_TIMER_SECONDS),
                 ));
 
                 updates
             }
 
-            ArenaInput::Turn(direction) => {
+            ArenaInput::Turn(direction) | ArenaInput::TurnAt(direction, _) => {
                 let lightcycle = match arena.lightcycles.get(&player_id) {
                     Some(lightcycle) => lightcycle,
                     None => {
@@ -86,7 +114,6 @@ impl ArenaInput {
                         return vec![];
                     }
                     Some(started) => {
-                        let now = Utc::now();
                         if now < started {
                             trace!("Refusing to turn lightcycle before arena has started");
                             return vec![];
@@ -104,11 +131,106 @@ impl ArenaInput {
                     return vec![];
                 }
 
-                return vec![
+                if let Some(updates) = client_tick.and_then(|client_tick| {
+                    lag_compensated_turn(arena, player_id, lightcycle, direction, client_tick)
+                }) {
+                    return updates;
+                }
+
+                vec![
                     ArenaUpdate::UpdateLightribbonAppendPoint(player_id, lightcycle.position),
                     ArenaUpdate::UpdateLightcycleDirection(player_id, direction),
-                ];
+                ]
+            }
+        }
+    }
+}
+
+///
+/// Places a turn where the turning client saw their lightcycle at `client_tick`, then moves
+/// the lightcycle along its new direction by the distance it has travelled since.
+///
+/// The client's tick is only trusted as far back as its measured round trip time
+/// (and never more than ARENA_LAG_COMPENSATION_MAX_TICKS), so a client claiming an old tick
+/// can't rewind further than its connection warrants.
+///
+/// Returns None, so that the turn is made at the lightcycle's current position instead,
+/// if there is nothing to rewind or if the rewound path would collide with anything.
+///
+fn lag_compensated_turn(
+    arena: &Arena,
+    player_id: PlayerId,
+    lightcycle: &Lightcycle,
+    direction: Direction,
+    client_tick: u64,
+) -> Option<Vec<ArenaUpdate>> {
+    let trail = arena.lightcycle_trails.get(&player_id)?;
+    if trail.direction != Some(lightcycle.direction) {
+        return None;
+    }
+
+    let latency_ticks = match arena.pings.get(&player_id) {
+        Some(ping) if arena.tick_seconds > 0.0 => {
+            (*ping as f64 / 1000.0 / arena.tick_seconds).ceil() as u64
+        }
+        _ => 0,
+    };
+    let rewind = arena
+        .tick
+        .saturating_sub(client_tick)
+        .min(latency_ticks)
+        .min(ARENA_LAG_COMPENSATION_MAX_TICKS) as usize;
+    let rewind = rewind.min(trail.positions.len().saturating_sub(1));
+    if rewind == 0 {
+        return None;
+    }
+    let turn_point = trail.positions[trail.positions.len() - 1 - rewind];
+
+    // the turn point must be on the latest (straight) segment of the lightribbon
+    let lightribbon = arena.lightribbons.get(&player_id)?;
+    let segment_start = lightribbon.points[lightribbon.points.len().checked_sub(2)?];
+    if (turn_point - segment_start).dot(lightcycle.direction.as_velocity()) < 0.0 {
+        return None;
+    }
+
+    let position =
+        turn_point + direction.as_velocity() * (lightcycle.position - turn_point).length();
+    if position.x < 0.0 || position.y < 0.0 || position.x > arena.width || position.y > arena.height
+    {
+        trace!("Refusing to compensate turn which would leave the arena");
+        return None;
+    }
+
+    let path = ArenaLine {
+        from: turn_point.to_untyped(),
+        to: position.to_untyped(),
+    };
+    for (id, lightribbon) in arena.lightribbons.iter() {
+        let mut points = lightribbon.points.clone();
+        if *id == player_id {
+            // the part of this lightribbon beyond the turn point is about to be taken back
+            if let Some(latest_point) = points.last_mut() {
+                *latest_point = turn_point;
+            }
+        }
+
+        for line in points.windows(2) {
+            let line = ArenaLine {
+                from: line[0].to_untyped(),
+                to: line[1].to_untyped(),
+            };
+
+            if path.overlaps_segment(&line) || path.intersection(&line).is_some() {
+                trace!("Refusing to compensate turn which would collide");
+                return None;
             }
         }
     }
+
+    Some(vec![
+        ArenaUpdate::UpdateLightribbonReplaceLatestPoint(player_id, turn_point),
+        ArenaUpdate::UpdateLightribbonAppendPoint(player_id, position),
+        ArenaUpdate::UpdateLightcyclePosition(player_id, position),
+        ArenaUpdate::UpdateLightcycleDirection(player_id, direction),
+    ])
 }
@@ -1,6 +1,6 @@
 use super::*;
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ArenaUpdate {
     AddPlayer(PlayerId, Player),
     AddLightcycle(PlayerId, Lightcycle),
@@ -9,6 +9,10 @@ pub enum ArenaUpdate {
     Start(DateTime<Utc>),
     End,
     SetWinner(Option<PlayerId>),
+    /// A checksum of the arena at this point in the updates, see Arena::checksum.
+    Checksum(u32),
+
+    UpdatePlayerPing(PlayerId, u64),
 
     UpdateLightcyclePosition(PlayerId, ArenaPoint),
     UpdateLightcycleDirection(PlayerId, Direction),
@@ -23,7 +27,7 @@ pub enum ArenaUpdate {
 }
 
 impl ArenaUpdate {
-    pub fn apply<'s, 'arena>(&'s self, arena: &'arena mut Arena) -> &'arena mut Arena {
+    pub fn apply<'arena>(&self, arena: &'arena mut Arena) -> &'arena mut Arena {
         match self {
             ArenaUpdate::AddPlayer(player_id, player) => {
                 arena.players.insert(*player_id, player.clone());
@@ -35,9 +39,19 @@ impl ArenaUpdate {
                 arena.lightribbons.insert(*player_id, lightribbon.clone());
             }
 
-            ArenaUpdate::Start(start_at) => arena.started = Some(*start_at),
+            ArenaUpdate::Start(start_at) => {
+                arena.started = Some(*start_at);
+                arena.tick = 0;
+            }
             ArenaUpdate::End => arena.started = None,
             ArenaUpdate::SetWinner(winner) => arena.winner = *winner,
+            ArenaUpdate::Checksum(_) => {}
+
+            ArenaUpdate::UpdatePlayerPing(player_id, ping) => {
+                if arena.players.contains_key(player_id) {
+                    arena.pings.insert(*player_id, *ping);
+                }
+            }
 
             ArenaUpdate::UpdateLightcyclePosition(player_id, position) => {
                 let lightcycle = match arena.lightcycles.get_mut(player_id) {
@@ -98,13 +112,14 @@ impl ArenaUpdate {
             }
 
             ArenaUpdate::RemovePlayer(player_id) => {
-                arena.players.remove(player_id);
+                arena.players.shift_remove(player_id);
+                arena.pings.shift_remove(player_id);
             }
             ArenaUpdate::RemoveLightcycle(player_id) => {
-                arena.lightcycles.remove(player_id);
+                arena.lightcycles.shift_remove(player_id);
             }
             ArenaUpdate::RemoveLightribbon(player_id) => {
-                arena.lightribbons.remove(player_id);
+                arena.lightribbons.shift_remove(player_id);
             }
         }
 
@@ -51,35 +51,24 @@ const SPAWNPOINTS: [(f64, f64, Direction); ARENA_MAX_PLAYERS] = [
     ),
 ];
 
-pub fn calculate_spawnpoints(player_ids: Vec<PlayerId>) -> Vec<(PlayerId, ArenaPoint, Direction)> {
-    let mut spawnpoints: Vec<(PlayerId, ArenaPoint, Direction)> = Vec::new();
-    let mut spawnpoints_used: Vec<usize> = Vec::new();
-
-    for player_id in player_ids {
-        // check if no spawnpoints remain
-        if spawnpoints_used.len() >= SPAWNPOINTS.len() {
-            error!("No spawnpoints remain!");
-            break;
-        }
-
-        // randomly select an available spawnpoint
-        let mut selected_spawnpoint = OsRng.next_u32() as usize % SPAWNPOINTS.len();
-        while spawnpoints_used
-            .iter()
-            .any(|spawnpoint| *spawnpoint == selected_spawnpoint)
-        {
-            selected_spawnpoint = OsRng.next_u32() as usize % SPAWNPOINTS.len();
-        }
-        spawnpoints_used.push(selected_spawnpoint);
+pub fn generate_arena_code() -> String {
+    (0..ARENA_CODE_LENGTH)
+        .map(|_| ARENA_CODE_ALPHABET[OsRng.next_u32() as usize % ARENA_CODE_ALPHABET.len()] as char)
+        .collect()
+}
 
-        // add the selected spawnpoint to our spawnpoints vector
-        let selected_spawnpoint = SPAWNPOINTS[selected_spawnpoint];
-        spawnpoints.push((
-            player_id,
-            ArenaPoint::new(selected_spawnpoint.0, selected_spawnpoint.1),
-            selected_spawnpoint.2,
-        ));
+///
+/// Assigns spawnpoints to players in join order, so that a round is decided by its inputs alone
+/// (the spawnpoints are listed so that each pair of players starts opposite one another).
+///
+pub fn calculate_spawnpoints(player_ids: Vec<PlayerId>) -> Vec<(PlayerId, ArenaPoint, Direction)> {
+    if player_ids.len() > SPAWNPOINTS.len() {
+        error!("No spawnpoints remain!");
     }
 
-    spawnpoints
+    player_ids
+        .into_iter()
+        .zip(SPAWNPOINTS.iter())
+        .map(|(player_id, (x, y, direction))| (player_id, ArenaPoint::new(*x, *y), *direction))
+        .collect()
 }
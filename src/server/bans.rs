@@ -0,0 +1,128 @@
+use serde_derive::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+const CONFIGURED_BAN_REASON: &str = "Banned in the server's configuration";
+
+/// An ip address banned from connecting.
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    pub ip_address: IpAddr,
+    pub reason: String,
+    /// Seconds until the ban expires, if it ever does.
+    pub expires_in_seconds: Option<u64>,
+}
+
+///
+/// The ip addresses which are turned away before their websockets are opened.
+///
+/// Bans come from the config (and last as long as the server runs),
+/// or are added by admins at runtime (optionally expiring).
+/// The list is shared between the server, which adds and lifts bans,
+/// and the web server, which checks every new connection against it.
+///
+#[derive(Debug, Default)]
+pub struct BanList {
+    configured: HashSet<IpAddr>,
+    bans: Mutex<HashMap<IpAddr, BanEntry>>,
+}
+
+#[derive(Debug)]
+struct BanEntry {
+    reason: String,
+    expires_at: Option<Instant>,
+}
+
+impl BanEntry {
+    fn describe(&self, ip_address: IpAddr) -> Ban {
+        Ban {
+            ip_address,
+            reason: self.reason.clone(),
+            expires_in_seconds: self.expires_at.map(|expires_at| {
+                expires_at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs()
+            }),
+        }
+    }
+
+    fn has_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl BanList {
+    pub fn new(configured: impl IntoIterator<Item = IpAddr>) -> Self {
+        Self {
+            configured: configured.into_iter().collect(),
+            bans: Default::default(),
+        }
+    }
+
+    pub fn ban(&self, ip_address: IpAddr, reason: String, duration: Option<Duration>) -> Ban {
+        let entry = BanEntry {
+            reason,
+            expires_at: duration.map(|duration| Instant::now() + duration),
+        };
+        let ban = entry.describe(ip_address);
+        self.lock().insert(ip_address, entry);
+
+        ban
+    }
+
+    /// Lifts a runtime ban, returning whether there was one.
+    pub fn unban(&self, ip_address: IpAddr) -> bool {
+        self.lock().remove(&ip_address).is_some()
+    }
+
+    pub fn is_configured(&self, ip_address: IpAddr) -> bool {
+        self.configured.contains(&ip_address)
+    }
+
+    /// Returns the ban on the given ip address, unless there is none or it has expired.
+    pub fn get(&self, ip_address: IpAddr) -> Option<Ban> {
+        if self.is_configured(ip_address) {
+            return Some(configured_ban(ip_address));
+        }
+
+        let mut bans = self.lock();
+        match bans.get(&ip_address) {
+            Some(entry) if entry.has_expired(Instant::now()) => {
+                bans.remove(&ip_address);
+                None
+            }
+            entry => entry.map(|entry| entry.describe(ip_address)),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Ban> {
+        let now = Instant::now();
+        let mut bans = self.lock();
+        bans.retain(|_, entry| !entry.has_expired(now));
+
+        self.configured
+            .iter()
+            .map(|ip_address| configured_ban(*ip_address))
+            .chain(
+                bans.iter()
+                    .filter(|(ip_address, _)| !self.is_configured(**ip_address))
+                    .map(|(ip_address, entry)| entry.describe(*ip_address)),
+            )
+            .collect()
+    }
+
+    // the map is left consistent by every method, so it's still usable if a holder panicked
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, BanEntry>> {
+        self.bans.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn configured_ban(ip_address: IpAddr) -> Ban {
+    Ban {
+        ip_address,
+        reason: CONFIGURED_BAN_REASON.to_owned(),
+        expires_in_seconds: None,
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Error, anyhow};
+use chrono::{DateTime, Duration as OldDuration, Utc};
+use serde_derive::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+
+use crate::server::{ArenaId, ClientId, PlayerId};
+
+const CHAT_MESSAGE_MAX_LENGTH: usize = 200;
+const CHAT_HISTORY_LENGTH: usize = 50;
+const CHAT_RATE_LIMIT_MESSAGES: usize = 5;
+const CHAT_RATE_LIMIT_SECONDS: i64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChatMessage {
+    pub player_id: PlayerId,
+    pub text: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+///
+/// A moderation hook which is run on the text of every chat message before it is sent.
+///
+pub trait ChatFilter: Debug + Send + Sync {
+    /// Returns the text to send (which may be altered), or None to refuse the message.
+    fn filter(&self, text: &str) -> Option<String>;
+}
+
+/// WordListFilter masks each listed word (case-insensitively) with asterisks.
+#[derive(Debug, Default)]
+pub struct WordListFilter {
+    words: Vec<String>,
+}
+
+impl WordListFilter {
+    pub fn new(words: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| word.as_ref().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl ChatFilter for WordListFilter {
+    fn filter(&self, text: &str) -> Option<String> {
+        let filtered = text
+            .split(' ')
+            .map(|word| {
+                let normalized: String = word
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .collect::<String>()
+                    .to_lowercase();
+
+                if self.words.contains(&normalized) {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_owned()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Some(filtered)
+    }
+}
+
+///
+/// Chat keeps track of the chat history for each arena, and of how often each client is chatting.
+///
+#[derive(Debug)]
+pub struct Chat {
+    filter: Box<dyn ChatFilter>,
+    histories: HashMap<ArenaId, VecDeque<ChatMessage>>,
+    recently_sent: HashMap<ClientId, VecDeque<DateTime<Utc>>>,
+}
+
+impl Default for Chat {
+    fn default() -> Self {
+        Self::with_filter(WordListFilter::default())
+    }
+}
+
+impl Chat {
+    pub fn with_filter(filter: impl ChatFilter + 'static) -> Self {
+        Self {
+            filter: Box::new(filter),
+            histories: Default::default(),
+            recently_sent: Default::default(),
+        }
+    }
+
+    ///
+    /// Validates, rate limits and filters a message from a client,
+    /// then records it in the arena's history.
+    ///
+    pub fn post(
+        &mut self,
+        client_id: ClientId,
+        arena_id: ArenaId,
+        player_id: PlayerId,
+        text: &str,
+    ) -> Result<ChatMessage, Error> {
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        let text = text.trim();
+
+        if text.is_empty() {
+            return Err(anyhow!("Chat message is empty"));
+        }
+        if text.chars().count() > CHAT_MESSAGE_MAX_LENGTH {
+            return Err(anyhow!(
+                "Chat message is longer than {} characters",
+                CHAT_MESSAGE_MAX_LENGTH
+            ));
+        }
+
+        let now = Utc::now();
+        let recently_sent = self.recently_sent.entry(client_id).or_default();
+        while recently_sent
+            .front()
+            .is_some_and(|sent_at| *sent_at < now - OldDuration::seconds(CHAT_RATE_LIMIT_SECONDS))
+        {
+            recently_sent.pop_front();
+        }
+        if recently_sent.len() >= CHAT_RATE_LIMIT_MESSAGES {
+            return Err(anyhow!("Client {} is chatting too quickly", client_id));
+        }
+
+        let text = self
+            .filter
+            .filter(text)
+            .ok_or_else(|| anyhow!("Chat message was refused by the filter"))?;
+
+        recently_sent.push_back(now);
+
+        let message = ChatMessage {
+            player_id,
+            text,
+            sent_at: now,
+        };
+
+        let history = self.histories.entry(arena_id).or_default();
+        if history.len() >= CHAT_HISTORY_LENGTH {
+            history.pop_front();
+        }
+        history.push_back(message.clone());
+
+        Ok(message)
+    }
+
+    pub fn history(&self, arena_id: ArenaId) -> Vec<ChatMessage> {
+        self.histories
+            .get(&arena_id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn remove_client(&mut self, client_id: ClientId) {
+        self.recently_sent.remove(&client_id);
+    }
+
+    pub fn remove_arena(&mut self, arena_id: ArenaId) {
+        self.histories.remove(&arena_id);
+    }
+}
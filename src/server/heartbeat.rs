@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The server is considered unhealthy once it has missed this many ticks in a row.
+const HEARTBEAT_MISSED_TICKS: u32 = 20;
+
+///
+/// Beats once per server tick, so that other tasks can tell whether the server loop
+/// is still running (rather than stalled or stopped).
+///
+#[derive(Debug)]
+pub struct Heartbeat {
+    created_at: Instant,
+    tick_duration: Duration,
+    /// Milliseconds since `created_at` of the latest tick, plus one (zero before the first tick).
+    last_tick_at: AtomicU64,
+}
+
+impl Heartbeat {
+    pub fn new(tick_duration: Duration) -> Self {
+        Self {
+            created_at: Instant::now(),
+            tick_duration,
+            last_tick_at: AtomicU64::new(0),
+        }
+    }
+
+    pub fn beat(&self) {
+        let now = self.created_at.elapsed().as_millis() as u64;
+        self.last_tick_at.store(now + 1, Ordering::Relaxed);
+    }
+
+    /// How long ago the server last ticked, or None if it hasn't ticked yet.
+    pub fn last_tick_age(&self) -> Option<Duration> {
+        match self.last_tick_at.load(Ordering::Relaxed) {
+            0 => None,
+            last_tick_at => Some(
+                self.created_at
+                    .elapsed()
+                    .saturating_sub(Duration::from_millis(last_tick_at - 1)),
+            ),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.last_tick_age()
+            .is_some_and(|age| age <= self.tick_duration * HEARTBEAT_MISSED_TICKS)
+    }
+}
@@ -0,0 +1,122 @@
+use serde_derive::Deserialize;
+
+use super::arena::ARENA_MAX_PLAYERS;
+use crate::server::{Arena, ClientId, Player};
+
+/// The number of queued players with matching preferences required to open a new arena.
+const QUICK_MATCH_MIN_PLAYERS: usize = 2;
+
+/// The kind of arena a player would like to be matched into.
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq, Deserialize)]
+pub struct MatchPreferences {
+    /// The preferred max number of players in the arena, or None for any size.
+    #[serde(default)]
+    pub size: Option<usize>,
+}
+
+impl MatchPreferences {
+    /// Clamps the preferences to sizes an arena can actually have.
+    pub fn normalized(self) -> Self {
+        Self {
+            size: self
+                .size
+                .map(|size| size.clamp(QUICK_MATCH_MIN_PLAYERS, ARENA_MAX_PLAYERS)),
+        }
+    }
+
+    /// Returns true if a player with these preferences can be placed into the given arena.
+    pub fn accepts(&self, arena: &Arena) -> bool {
+        arena.started.is_none()
+            && arena.players.len() < arena.max_players
+            && self.size.is_none_or(|size| size == arena.max_players)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueuedPlayer {
+    pub client_id: ClientId,
+    pub player: Player,
+    pub preferences: MatchPreferences,
+    /// The last queue position sent to the client.
+    pub notified_position: Option<usize>,
+}
+
+/// MatchmakingQueue holds the clients waiting for a quick match, in the order they queued.
+#[derive(Debug, Default)]
+pub struct MatchmakingQueue {
+    queue: Vec<QueuedPlayer>,
+}
+
+impl MatchmakingQueue {
+    /// Adds a client to the back of the queue, replacing any existing entry for that client.
+    pub fn enqueue(&mut self, client_id: ClientId, player: Player, preferences: MatchPreferences) {
+        self.remove(client_id);
+        self.queue.push(QueuedPlayer {
+            client_id,
+            player,
+            preferences: preferences.normalized(),
+            notified_position: None,
+        });
+    }
+
+    pub fn remove(&mut self, client_id: ClientId) -> Option<QueuedPlayer> {
+        let index = self
+            .queue
+            .iter()
+            .position(|queued| queued.client_id == client_id)?;
+
+        Some(self.queue.remove(index))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Removes and returns the first queued player who would accept the given arena.
+    pub fn take_for_arena(&mut self, arena: &Arena) -> Option<QueuedPlayer> {
+        let index = self
+            .queue
+            .iter()
+            .position(|queued| queued.preferences.accepts(arena))?;
+
+        Some(self.queue.remove(index))
+    }
+
+    /// Returns the longest-waiting player from the first group of players with matching
+    /// preferences which is large enough to open a new arena.
+    pub fn ready_group(&self) -> Option<&QueuedPlayer> {
+        self.queue.iter().find(|queued| {
+            self.queue
+                .iter()
+                .filter(|other| other.preferences == queued.preferences)
+                .count()
+                >= QUICK_MATCH_MIN_PLAYERS
+        })
+    }
+
+    /// Returns the clients whose position in the queue has changed since they were last notified,
+    /// along with their new position (counting from 1 among players with the same preferences).
+    pub fn take_position_changes(&mut self) -> Vec<(ClientId, usize)> {
+        let mut changes = Vec::new();
+
+        for index in 0..self.queue.len() {
+            let preferences = self.queue[index].preferences;
+            let position = 1 + self.queue[..index]
+                .iter()
+                .filter(|queued| queued.preferences == preferences)
+                .count();
+
+            let queued = &mut self.queue[index];
+            if queued.notified_position != Some(position) {
+                queued.notified_position = Some(position);
+                changes.push((queued.client_id, position));
+            }
+        }
+
+        changes
+    }
+}
@@ -1,14 +1,28 @@
 pub use incoming::Message as MessageIn;
 pub use incoming::MessagePayload as MessageInPayload;
+pub use incoming::Query as ServerQuery;
 pub use outgoing::Message as MessageOut;
+pub use outgoing::ServerStatus;
+
+/// The websocket close code sent to clients when the server shuts down ("going away").
+pub(crate) const CLOSE_CODE_GOING_AWAY: u16 = 1001;
+/// The websocket close code sent to clients who are kicked, banned or flooding the server
+/// ("policy violation").
+pub(crate) const CLOSE_CODE_POLICY_VIOLATION: u16 = 1008;
+/// The websocket close code sent to clients whose arena is closed ("normal closure").
+pub(crate) const CLOSE_CODE_NORMAL: u16 = 1000;
 
 ///
 /// Server to client messages
 ///
 pub mod outgoing {
+    use chrono::{DateTime, Utc};
     use serde_derive::Serialize;
 
-    use crate::server::{Arena, ArenaId, ArenaOverview, ArenaUpdate, PlayerId};
+    use crate::server::{
+        Arena, ArenaId, ArenaOverview, ArenaUpdate, ChatMessage, EntityMap, Lightcycle, Player,
+        PlayerId,
+    };
 
     ///
     /// Outgoing messages
@@ -16,16 +30,115 @@ pub mod outgoing {
     #[derive(Debug, Clone, Serialize)]
     pub enum Message {
         ArenaList(Vec<ArenaOverview>),
-        ArenaJoined(ArenaId, PlayerId),
+        ArenaJoined(ArenaId, PlayerId, Player),
+        QuickMatchPosition(usize),
 
         ArenaState(Box<Arena>),
-        ArenaStatePatch(Vec<ArenaUpdate>),
+        ArenaStatePatch(ArenaStatePatch),
+        /// The position of every lightcycle, for clients to interpolate between.
+        LightcycleSnapshot {
+            tick: u64,
+            timestamp: DateTime<Utc>,
+            lightcycles: EntityMap<Lightcycle>,
+        },
+
+        ChatMessage(ChatMessage),
+        ChatHistory(Vec<ChatMessage>),
+
+        /// Acknowledges one of the client's inputs, and the arena tick it was applied on.
+        InputAck {
+            sequence: u32,
+            tick: u64,
+        },
+        /// Tells the client that one of its inputs was refused, or dropped before it was made.
+        InputRejected {
+            sequence: u32,
+            reason: InputRejectedReason,
+        },
+
+        /// Warns that the server is shutting down, once any round in progress has finished
+        /// (but within `in_seconds` at most).
+        ServerShutdown {
+            in_seconds: u64,
+            reason: String,
+        },
+        /// A message from the server's operators, shown to every client.
+        Announcement {
+            text: String,
+        },
+        /// Closes the client's websocket with the given close code.
+        #[serde(skip)]
+        Close {
+            code: u16,
+            reason: String,
+        },
+    }
+
+    /// Why one of a client's inputs wasn't made.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    pub enum InputRejectedReason {
+        /// The arena refused or dropped the input (such as a turn made before the round started).
+        Dropped,
+        /// The input arrived after another with the same or a later sequence number.
+        OutOfOrder,
+    }
+
+    ///
+    /// A run of arena updates, stamped with the server tick and time they bring the arena up to,
+    /// and with the rates (per second) at which the server simulates ticks and sends patches.
+    ///
+    /// When patches are sent less often than ticks are simulated,
+    /// each patch holds the updates from every tick since the last one.
+    ///
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ArenaStatePatch {
+        pub tick: u64,
+        pub timestamp: DateTime<Utc>,
+        pub tick_rate: u64,
+        pub send_rate: u64,
+        pub updates: Vec<ArenaUpdate>,
+    }
+
+    ///
+    /// A summary of the server's state, for dashboards and bots.
+    ///
+    #[derive(Debug, Clone, Serialize)]
+    pub struct ServerStatus {
+        pub version: &'static str,
+        pub uptime_seconds: u64,
+        pub tick_rate: u64,
+        pub send_rate: u64,
+        pub clients: usize,
+        pub players: usize,
+        pub arenas: usize,
+        pub arenas_started: usize,
+        pub quick_match_queued: usize,
+        pub shutting_down: bool,
     }
 
     impl Message {
         pub fn to_json(&self) -> Result<String, serde_json::error::Error> {
             serde_json::to_string(self)
         }
+
+        /// The name of the message type, as used in its json.
+        pub fn name(&self) -> &'static str {
+            match self {
+                Message::ArenaList(_) => "ArenaList",
+                Message::ArenaJoined(..) => "ArenaJoined",
+                Message::QuickMatchPosition(_) => "QuickMatchPosition",
+                Message::ArenaState(_) => "ArenaState",
+                Message::ArenaStatePatch(_) => "ArenaStatePatch",
+                Message::LightcycleSnapshot { .. } => "LightcycleSnapshot",
+                Message::ChatMessage(_) => "ChatMessage",
+                Message::ChatHistory(_) => "ChatHistory",
+                Message::InputAck { .. } => "InputAck",
+                Message::InputRejected { .. } => "InputRejected",
+                Message::ServerShutdown { .. } => "ServerShutdown",
+                Message::Announcement { .. } => "Announcement",
+                Message::Close { .. } => "Close",
+            }
+        }
     }
 }
 
@@ -35,9 +148,15 @@ pub mod outgoing {
 pub mod incoming {
     use anyhow::Error;
     use serde_derive::Deserialize;
+    use std::net::IpAddr;
+    use std::time::Duration;
     use tokio::sync::mpsc::Sender;
+    use tokio::sync::oneshot;
 
-    use crate::server::{ArenaId, ClientId, Direction, MessageOut, Player};
+    use crate::server::{
+        AdminCommand, ArenaId, ArenaOverview, ClientId, Direction, MatchPreferences, MessageOut,
+        Player, Replay, ReplayControl, ReplayId, ServerStatus,
+    };
 
     ///
     /// Incoming messages
@@ -51,24 +170,89 @@ pub mod incoming {
     #[derive(Debug, Deserialize)]
     pub enum MessagePayload {
         #[serde(skip)]
-        Connect(Option<String>, Sender<MessageOut>),
+        Connect(Option<IpAddr>, Sender<MessageOut>),
         #[serde(skip)]
         Disconnect,
+        #[serde(skip)]
+        Latency(u64),
+        /// Sent by the server process itself (not by a client) when it is asked to stop.
+        #[serde(skip)]
+        Shutdown {
+            reason: String,
+            timeout: Duration,
+        },
+        /// Sent by the web server (not by a client) to read the server's state.
+        #[serde(skip)]
+        Query(Query),
+        /// Sent by the admin api (not by a client).
+        #[serde(skip)]
+        Admin(AdminCommand),
+        /// Sent by the server's own replay loading task (not by a client) once a replay
+        /// a client asked to watch has been read from disk.
+        #[serde(skip)]
+        ReplayLoaded(ReplayId, Box<Result<Replay, Error>>),
 
         GetArenaList,
         Join {
             player: Player,
             arena_id: Option<ArenaId>,
         },
+        JoinByCode {
+            player: Player,
+            arena_code: String,
+        },
+        QuickMatch {
+            player: Player,
+            #[serde(default)]
+            preferences: MatchPreferences,
+        },
+        LeaveQuickMatch,
+
+        Chat {
+            text: String,
+        },
+
+        WatchReplay {
+            replay_id: ReplayId,
+        },
+        ReplayControl(ReplayControl),
 
         Start,
-        Turn(Direction),
+        Turn(Turn),
+
+        /// Sent by clients whose arena no longer matches a checksum from the server.
+        DesyncDetected,
+    }
+
+    ///
+    /// A request for some of the server's state, answered on the enclosed channel.
+    ///
+    #[derive(Debug)]
+    pub enum Query {
+        ArenaList(oneshot::Sender<Vec<ArenaOverview>>),
+        Arena(ArenaId, oneshot::Sender<Option<ArenaOverview>>),
+        Status(oneshot::Sender<ServerStatus>),
+    }
+
+    ///
+    /// A turn, tagged with the client's input sequence number and the arena tick the client
+    /// was showing when the turn was made. Older clients send just the direction.
+    ///
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    pub enum Turn {
+        Sequenced {
+            direction: Direction,
+            sequence: u32,
+            tick: u64,
+        },
+        Direction(Direction),
     }
 
     impl Message {
         pub fn connect(
             client_id: ClientId,
-            ip_address: Option<String>,
+            ip_address: Option<IpAddr>,
             tx: Sender<MessageOut>,
         ) -> Self {
             Self {
@@ -84,11 +268,79 @@ pub mod incoming {
             }
         }
 
+        pub fn latency(client_id: ClientId, latency_milliseconds: u64) -> Self {
+            Self {
+                client_id,
+                payload: MessagePayload::Latency(latency_milliseconds),
+            }
+        }
+
+        pub fn shutdown(reason: &str, timeout: Duration) -> Self {
+            Self {
+                client_id: ClientId::default(),
+                payload: MessagePayload::Shutdown {
+                    reason: reason.to_owned(),
+                    timeout,
+                },
+            }
+        }
+
+        pub fn query(query: Query) -> Self {
+            Self {
+                client_id: ClientId::default(),
+                payload: MessagePayload::Query(query),
+            }
+        }
+
+        pub fn admin(command: AdminCommand) -> Self {
+            Self {
+                client_id: ClientId::default(),
+                payload: MessagePayload::Admin(command),
+            }
+        }
+
+        pub fn replay_loaded(
+            client_id: ClientId,
+            replay_id: ReplayId,
+            replay: Result<Replay, Error>,
+        ) -> Self {
+            Self {
+                client_id,
+                payload: MessagePayload::ReplayLoaded(replay_id, Box::new(replay)),
+            }
+        }
+
         pub fn from_json(client_id: ClientId, json: &str) -> Result<Self, Error> {
             Ok(Self {
                 client_id,
-                payload: serde_json::from_str(&json)?,
+                payload: serde_json::from_str(json)?,
             })
         }
     }
+
+    impl MessagePayload {
+        /// The name of the message type, as used in its json.
+        pub fn name(&self) -> &'static str {
+            match self {
+                MessagePayload::Connect(..) => "Connect",
+                MessagePayload::Disconnect => "Disconnect",
+                MessagePayload::Latency(_) => "Latency",
+                MessagePayload::Shutdown { .. } => "Shutdown",
+                MessagePayload::Query(_) => "Query",
+                MessagePayload::Admin(_) => "Admin",
+                MessagePayload::ReplayLoaded(..) => "ReplayLoaded",
+                MessagePayload::GetArenaList => "GetArenaList",
+                MessagePayload::Join { .. } => "Join",
+                MessagePayload::JoinByCode { .. } => "JoinByCode",
+                MessagePayload::QuickMatch { .. } => "QuickMatch",
+                MessagePayload::LeaveQuickMatch => "LeaveQuickMatch",
+                MessagePayload::Chat { .. } => "Chat",
+                MessagePayload::WatchReplay { .. } => "WatchReplay",
+                MessagePayload::ReplayControl(_) => "ReplayControl",
+                MessagePayload::Start => "Start",
+                MessagePayload::Turn(_) => "Turn",
+                MessagePayload::DesyncDetected => "DesyncDetected",
+            }
+        }
+    }
 }
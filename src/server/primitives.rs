@@ -1,6 +1,11 @@
+use anyhow::{Error, anyhow};
 use euclid::{Point2D, Vector2D};
 use lyon_geom::LineSegment;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Instant;
 use tokio::sync::mpsc::Sender;
 
 use crate::new_id_type;
@@ -9,6 +14,15 @@ use crate::server::MessageOut;
 new_id_type!(ClientId);
 new_id_type!(PlayerId);
 new_id_type!(ArenaId);
+new_id_type!(ReplayId);
+
+pub const PLAYER_NAME_MAX_LENGTH: usize = 10;
+pub const PLAYER_TAG_MAX_LENGTH: usize = 4;
+/// Player colors closer together than this (in rgb space) are considered too similar.
+const PLAYER_COLOR_MIN_DISTANCE: u32 = 64;
+const PLAYER_COLOR_MIN_CONTRAST: f64 = 3.0;
+/// The `--darkaqua` background the client draws the arena over.
+const ARENA_BACKGROUND_COLOR: (u8, u8, u8) = (0x11, 0x1b, 0x1a);
 
 /// The euclidian space in which ArenaVectors and ArenaPoints operate.
 pub struct ArenaSpace;
@@ -22,11 +36,20 @@ pub type ArenaLine = LineSegment<f64>;
 #[derive(Debug)]
 pub struct Client {
     pub id: ClientId,
-    pub ip_address: Option<String>,
+    pub ip_address: Option<IpAddr>,
     pub tx: Sender<MessageOut>,
     pub player: Option<PlayerId>,
     pub arena: Option<ArenaId>,
     pub updates_sent_so_far: usize,
+    /// Whether the client should be sent the whole ArenaState again on the next send,
+    /// after reporting a desync.
+    pub resend_state: bool,
+    /// When the state was last resent for a desync report, to limit how often that happens.
+    pub desync_resent_at: Option<Instant>,
+    /// The sequence number of the latest input received from the client.
+    pub last_input_sequence: Option<u32>,
+    /// The round trip time of the client's connection, as of its latest pong.
+    pub latency_milliseconds: Option<u64>,
 }
 
 #[derive(Default, Debug, Clone, Hash, PartialEq, Serialize, Deserialize)]
@@ -35,23 +58,208 @@ pub struct Player {
     pub id: PlayerId,
     pub name: String,
     pub color: PlayerColor,
+    #[serde(default)]
+    pub ribbon_style: RibbonStyle,
+    #[serde(default)]
+    pub tag: Option<String>,
 }
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+impl Player {
+    ///
+    /// Normalizes the player's name the same way the client does:
+    /// trimmed, lowercase, spaces replaced with underscores and at most
+    /// PLAYER_NAME_MAX_LENGTH letters, digits, underscores or dashes.
+    ///
+    /// Tags are uppercased and limited to PLAYER_TAG_MAX_LENGTH letters or digits.
+    ///
+    pub fn normalized(mut self) -> Result<Self, Error> {
+        self.name = self
+            .name
+            .trim()
+            .to_lowercase()
+            .replace(' ', "_")
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .take(PLAYER_NAME_MAX_LENGTH)
+            .collect();
+
+        if self.name.is_empty() {
+            return Err(anyhow!("Player name is empty"));
+        }
+
+        self.tag = self
+            .tag
+            .map(|tag| {
+                tag.to_uppercase()
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .take(PLAYER_TAG_MAX_LENGTH)
+                    .collect::<String>()
+            })
+            .filter(|tag| !tag.is_empty());
+
+        if !self.color.is_visible() {
+            return Err(anyhow!(
+                "Player color {} is too dark to see against the arena",
+                self.color
+            ));
+        }
+
+        Ok(self)
+    }
+}
+
+///
+/// A player's color, sent over the wire as either one of the original
+/// color names (e.g. `"blue"`) or as an rgb hex string (e.g. `"#00c2cc"`).
+///
+#[derive(Default, Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum PlayerColor {
     Blue,
     Green,
     Orange,
     Purple,
     Red,
+    #[default]
     White,
+    Rgb(u8, u8, u8),
 }
 
-impl Default for PlayerColor {
-    fn default() -> Self {
-        PlayerColor::White
+impl PlayerColor {
+    pub const NAMED: [PlayerColor; 6] = [
+        PlayerColor::Blue,
+        PlayerColor::Green,
+        PlayerColor::Orange,
+        PlayerColor::Purple,
+        PlayerColor::Red,
+        PlayerColor::White,
+    ];
+
+    pub fn as_rgb(self) -> (u8, u8, u8) {
+        match self {
+            PlayerColor::Blue => (0x00, 0xc2, 0xcc),
+            PlayerColor::Green => (0x2e, 0xe5, 0x3d),
+            PlayerColor::Orange => (0xf2, 0xd9, 0x1a),
+            PlayerColor::Purple => (0x8a, 0x2e, 0xe5),
+            PlayerColor::Red => (0xe5, 0x48, 0x2e),
+            PlayerColor::White => (0xe5, 0xfe, 0xff),
+            PlayerColor::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// The squared euclidian distance between two colors in rgb space.
+    pub fn distance_squared(self, to: PlayerColor) -> u32 {
+        let (r1, g1, b1) = self.as_rgb();
+        let (r2, g2, b2) = to.as_rgb();
+
+        [(r1, r2), (g1, g2), (b1, b2)]
+            .iter()
+            .map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32)
+            .sum()
+    }
+
+    /// Returns true if the two colors are close enough to be mistaken for each other.
+    pub fn is_similar(self, to: PlayerColor) -> bool {
+        self.distance_squared(to) < PLAYER_COLOR_MIN_DISTANCE.pow(2)
     }
+
+    /// Returns true if the color stands out enough against the arena background.
+    pub fn is_visible(self) -> bool {
+        contrast_ratio(self.as_rgb(), ARENA_BACKGROUND_COLOR) >= PLAYER_COLOR_MIN_CONTRAST
+    }
+
+    /// Returns the named color closest to this one which isn't similar to any in `used`, if any.
+    pub fn nearest_free(self, used: &[PlayerColor]) -> Option<PlayerColor> {
+        PlayerColor::NAMED
+            .iter()
+            .copied()
+            .filter(|color| !used.iter().any(|used| color.is_similar(*used)))
+            .min_by_key(|color| self.distance_squared(*color))
+    }
+}
+
+impl fmt::Display for PlayerColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerColor::Blue => write!(f, "blue"),
+            PlayerColor::Green => write!(f, "green"),
+            PlayerColor::Orange => write!(f, "orange"),
+            PlayerColor::Purple => write!(f, "purple"),
+            PlayerColor::Red => write!(f, "red"),
+            PlayerColor::White => write!(f, "white"),
+            PlayerColor::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+impl FromStr for PlayerColor {
+    type Err = Error;
+
+    fn from_str(color: &str) -> Result<Self, Self::Err> {
+        let color = color.trim().to_lowercase();
+
+        if let Some(named) = PlayerColor::NAMED
+            .iter()
+            .find(|named| named.to_string() == color)
+        {
+            return Ok(*named);
+        }
+
+        let hex = color
+            .strip_prefix('#')
+            // checked before slicing by byte, which would panic inside a multibyte character
+            .filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| anyhow!("Invalid player color: {}", color))?;
+        let channel = |index: usize| {
+            u8::from_str_radix(&hex[index..index + 2], 16)
+                .map_err(|_| anyhow!("Invalid player color: {}", color))
+        };
+
+        Ok(PlayerColor::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for PlayerColor {
+    type Error = Error;
+
+    fn try_from(color: String) -> Result<Self, Self::Error> {
+        color.parse()
+    }
+}
+
+impl From<PlayerColor> for String {
+    fn from(color: PlayerColor) -> Self {
+        color.to_string()
+    }
+}
+
+/// The WCAG contrast ratio between two colors, from 1 (identical) to 21 (black on white).
+fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+        let channel = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+    }
+
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+#[derive(Default, Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RibbonStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+    Glow,
 }
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,471 @@
+use anyhow::{Context, Error, anyhow};
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{error, info, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+use tokio::task;
+
+use crate::get_error_chain;
+use crate::server::{
+    Arena, ArenaId, ArenaInput, ArenaUpdate, DEFAULT_TICK_RATE, EntityMap, Player, PlayerId,
+    ReplayId,
+};
+
+///
+/// Replay files are gzipped json lines:
+///
+///   1. a ReplayHeader
+///   2. the Arena state at the start of the round
+///   3. one ReplayFrame per line until the end of the round
+///
+/// The version is bumped whenever this layout or the shape of ArenaUpdate changes.
+///
+pub const REPLAY_FORMAT_VERSION: u32 = 4;
+const REPLAY_EXTENSION: &str = "replay";
+const REPLAY_MIN_SPEED: f64 = 0.25;
+const REPLAY_MAX_SPEED: f64 = 8.0;
+
+/// ReplayHeader summarizes a replay, so that replays can be listed without reading them in full.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub id: ReplayId,
+    pub arena_name: String,
+    pub recorded_at: DateTime<Utc>,
+    pub duration_milliseconds: u64,
+    pub players: EntityMap<Player>,
+    pub winner: Option<PlayerId>,
+    /// The tick rate the round was simulated at.
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: u64,
+    /// The length of each tick the round was simulated with, in seconds
+    /// (exactly as the server stepped it, which the tick rate alone doesn't pin down).
+    #[serde(default)]
+    pub tick_seconds: f64,
+}
+
+fn default_tick_rate() -> u64 {
+    DEFAULT_TICK_RATE
+}
+
+///
+/// The updates from one server tick, timestamped relative to the start of the round,
+/// along with the turns made on that tick (so that the round can be re-simulated).
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    #[serde(rename = "t")]
+    pub milliseconds: u64,
+    /// The arena's tick once this frame's updates were applied.
+    #[serde(rename = "k")]
+    pub tick: u64,
+    #[serde(rename = "u")]
+    pub updates: Vec<ArenaUpdate>,
+    #[serde(rename = "i", default, skip_serializing_if = "Vec::is_empty")]
+    pub turns: Vec<(PlayerId, ArenaInput)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub initial_state: Arena,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn path(directory: &Path, id: ReplayId) -> PathBuf {
+        directory.join(format!("{}.{}", id, REPLAY_EXTENSION))
+    }
+
+    pub fn write(&self, directory: &Path) -> Result<PathBuf, Error> {
+        fs::create_dir_all(directory)
+            .with_context(|| anyhow!("Failed to create replay directory {:?}", directory))?;
+
+        let path = Replay::path(directory, self.header.id);
+        let partial_path = path.with_extension("partial");
+
+        let file = File::create(&partial_path)
+            .with_context(|| anyhow!("Failed to create replay file {:?}", partial_path))?;
+        let mut writer = GzEncoder::new(BufWriter::new(file), Compression::default());
+
+        serde_json::to_writer(&mut writer, &self.header)?;
+        writeln!(writer)?;
+        serde_json::to_writer(&mut writer, &self.initial_state)?;
+        writeln!(writer)?;
+        for frame in &self.frames {
+            serde_json::to_writer(&mut writer, frame)?;
+            writeln!(writer)?;
+        }
+        writer.finish()?.flush()?;
+
+        fs::rename(&partial_path, &path)
+            .with_context(|| anyhow!("Failed to move replay file into place at {:?}", path))?;
+
+        Ok(path)
+    }
+
+    ///
+    /// Writes the replay on the blocking thread pool,
+    /// then deletes the oldest replays beyond the retention limit.
+    ///
+    /// `saves_in_progress` is incremented until the replay has been written.
+    ///
+    pub fn save_in_background(
+        self,
+        directory: PathBuf,
+        retention: usize,
+        saves_in_progress: Arc<AtomicUsize>,
+    ) {
+        saves_in_progress.fetch_add(1, Ordering::SeqCst);
+        task::spawn_blocking(move || {
+            let result = self
+                .write(&directory)
+                .and_then(|path| {
+                    info!("Saved replay {:?}", path);
+                    enforce_retention(&directory, retention)
+                })
+                .context("Failed to save replay");
+
+            if let Err(error) = result {
+                error!("{}", get_error_chain(error));
+            }
+
+            saves_in_progress.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    pub fn read(path: &Path) -> Result<Replay, Error> {
+        let mut lines = Replay::open(path)?.lines();
+
+        let header = Replay::parse_header(path, &mut lines)?;
+        let initial_state: Arena = serde_json::from_str(
+            &lines
+                .next()
+                .ok_or_else(|| anyhow!("Replay file {:?} has no initial state", path))??,
+        )?;
+        let frames = lines
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<Result<Vec<ReplayFrame>, Error>>()
+            .with_context(|| anyhow!("Failed to read frames from replay file {:?}", path))?;
+
+        Ok(Replay {
+            header,
+            initial_state,
+            frames,
+        })
+    }
+
+    pub fn read_header(path: &Path) -> Result<ReplayHeader, Error> {
+        Replay::parse_header(path, &mut Replay::open(path)?.lines())
+    }
+
+    fn parse_header(
+        path: &Path,
+        lines: &mut impl Iterator<Item = Result<String, std::io::Error>>,
+    ) -> Result<ReplayHeader, Error> {
+        let header: ReplayHeader = serde_json::from_str(
+            &lines
+                .next()
+                .ok_or_else(|| anyhow!("Replay file {:?} is empty", path))??,
+        )?;
+
+        if header.version != REPLAY_FORMAT_VERSION {
+            return Err(anyhow!(
+                "Replay file {:?} has unsupported version {}",
+                path,
+                header.version
+            ));
+        }
+
+        Ok(header)
+    }
+
+    fn open(path: &Path) -> Result<BufReader<GzDecoder<File>>, Error> {
+        let file =
+            File::open(path).with_context(|| anyhow!("Failed to open replay file {:?}", path))?;
+
+        Ok(BufReader::new(GzDecoder::new(file)))
+    }
+}
+
+///
+/// Returns the headers of all of the replays in a directory, newest first.
+///
+pub fn list_replays(directory: &Path) -> Result<Vec<ReplayHeader>, Error> {
+    let mut headers: Vec<ReplayHeader> = replay_paths(directory)?
+        .iter()
+        .filter_map(|path| {
+            Replay::read_header(path)
+                .map_err(|error| warn!("Skipping unreadable replay: {}", error))
+                .ok()
+        })
+        .collect();
+
+    headers.sort_by_key(|header| Reverse(header.recorded_at));
+
+    Ok(headers)
+}
+
+///
+/// Deletes the oldest replays in a directory until only `keep` remain.
+///
+pub fn enforce_retention(directory: &Path, keep: usize) -> Result<(), Error> {
+    let mut paths: Vec<(PathBuf, SystemTime)> = replay_paths(directory)?
+        .into_iter()
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect();
+
+    if paths.len() <= keep {
+        return Ok(());
+    }
+
+    paths.sort_by_key(|(_, modified)| Reverse(*modified));
+    for (path, _) in paths.drain(keep..) {
+        fs::remove_file(&path)
+            .with_context(|| anyhow!("Failed to remove old replay {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+fn replay_paths(directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(fs::read_dir(directory)
+        .with_context(|| anyhow!("Failed to read replay directory {:?}", directory))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == REPLAY_EXTENSION))
+        .collect())
+}
+
+///
+/// ReplayRecorder watches the updates of every arena, and records each round
+/// from its ArenaUpdate::Start to its ArenaUpdate::End.
+///
+#[derive(Debug)]
+pub struct ReplayRecorder {
+    tick_rate: u64,
+    updates_seen_so_far: HashMap<ArenaId, usize>,
+    rounds: HashMap<ArenaId, RoundRecording>,
+}
+
+#[derive(Debug)]
+struct RoundRecording {
+    started_at: DateTime<Utc>,
+    initial_state: Arena,
+    frames: Vec<ReplayFrame>,
+}
+
+impl ReplayRecorder {
+    pub fn new(tick_rate: u64) -> Self {
+        Self {
+            tick_rate,
+            updates_seen_so_far: Default::default(),
+            rounds: Default::default(),
+        }
+    }
+
+    ///
+    /// Records the updates applied to an arena since it was last observed.
+    /// Should be called once per tick, after the arena has been updated.
+    ///
+    /// Returns the finished replay when a round ends.
+    ///
+    pub fn observe(&mut self, arena: &Arena) -> Option<Replay> {
+        let seen = self.updates_seen_so_far.entry(arena.id).or_default();
+        let updates = &arena.updates[(*seen).min(arena.updates.len())..];
+        *seen = arena.updates.len();
+
+        let round = match self.rounds.get_mut(&arena.id) {
+            Some(round) => round,
+            None => {
+                if updates
+                    .iter()
+                    .any(|update| matches!(update, ArenaUpdate::Start(_)))
+                {
+                    let mut initial_state = arena.clone();
+                    initial_state.clear_updates();
+
+                    self.rounds.insert(
+                        arena.id,
+                        RoundRecording {
+                            started_at: Utc::now(),
+                            initial_state,
+                            frames: Vec::new(),
+                        },
+                    );
+                }
+                return None;
+            }
+        };
+
+        let milliseconds = (Utc::now() - round.started_at).num_milliseconds().max(0) as u64;
+        if !updates.is_empty() || !arena.turns_processed.is_empty() {
+            round.frames.push(ReplayFrame {
+                milliseconds,
+                tick: arena.tick,
+                updates: updates.to_vec(),
+                turns: arena.turns_processed.clone(),
+            });
+        }
+
+        if !updates.contains(&ArenaUpdate::End) {
+            return None;
+        }
+
+        let round = self.rounds.remove(&arena.id)?;
+        Some(Replay {
+            header: ReplayHeader {
+                version: REPLAY_FORMAT_VERSION,
+                id: ReplayId::default(),
+                arena_name: arena.name.clone(),
+                recorded_at: round.started_at,
+                duration_milliseconds: milliseconds,
+                players: round.initial_state.players.clone(),
+                winner: arena.winner,
+                tick_rate: self.tick_rate,
+                tick_seconds: arena.tick_seconds(),
+            },
+            initial_state: round.initial_state,
+            frames: round.frames,
+        })
+    }
+
+    pub fn remove_arena(&mut self, arena_id: ArenaId) {
+        self.updates_seen_so_far.remove(&arena_id);
+        self.rounds.remove(&arena_id);
+    }
+}
+
+/// Playback controls for a client watching a replay.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum ReplayControl {
+    Pause,
+    Resume,
+    Seek(u64),
+    SetSpeed(f64),
+    Stop,
+}
+
+///
+/// ReplayPlayback steps through a replay in (scaled) real time, keeping track
+/// of the arena state at the current point so that it can be resent at any time.
+///
+#[derive(Debug)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    state: Arena,
+    next_frame: usize,
+    position_milliseconds: f64,
+    speed: f64,
+    paused: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        let state = replay.initial_state.clone();
+
+        Self {
+            replay,
+            state,
+            next_frame: 0,
+            position_milliseconds: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn state(&self) -> &Arena {
+        &self.state
+    }
+
+    pub fn tick_rate(&self) -> u64 {
+        self.replay.header.tick_rate
+    }
+
+    /// The tick of the recorded round which playback has reached.
+    pub fn tick(&self) -> u64 {
+        match self.replay.header.tick_seconds {
+            tick_seconds if tick_seconds > 0.0 => {
+                (self.position_milliseconds / 1000.0 / tick_seconds) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_frame >= self.replay.frames.len()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed.is_finite() {
+            self.speed = speed.clamp(REPLAY_MIN_SPEED, REPLAY_MAX_SPEED);
+        }
+    }
+
+    ///
+    /// Moves playback forward by `delta_time` seconds (scaled by the playback speed),
+    /// returning the updates which were passed along the way.
+    ///
+    pub fn advance(&mut self, delta_time: f64) -> Vec<ArenaUpdate> {
+        if self.paused {
+            return Vec::new();
+        }
+
+        self.position_milliseconds += delta_time * 1000.0 * self.speed;
+        self.play_until(self.position_milliseconds)
+    }
+
+    ///
+    /// Jumps to a point in the replay by rebuilding the arena state from the start of the round.
+    ///
+    pub fn seek(&mut self, milliseconds: u64) -> &Arena {
+        self.state = self.replay.initial_state.clone();
+        self.next_frame = 0;
+        self.position_milliseconds = milliseconds as f64;
+        self.play_until(self.position_milliseconds);
+
+        &self.state
+    }
+
+    fn play_until(&mut self, milliseconds: f64) -> Vec<ArenaUpdate> {
+        let mut updates = Vec::new();
+
+        while let Some(frame) = self.replay.frames.get(self.next_frame) {
+            if frame.milliseconds as f64 > milliseconds {
+                break;
+            }
+
+            for update in &frame.updates {
+                update.apply(&mut self.state);
+            }
+            updates.extend(frame.updates.iter().cloned());
+            self.next_frame += 1;
+        }
+
+        updates
+    }
+}
@@ -0,0 +1,112 @@
+use futures::future;
+use log::{error, info, warn};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc::Sender;
+
+use crate::server::MessageIn;
+
+const SHUTDOWN_REASON: &str = "The server is shutting down";
+
+///
+/// Lets the web server know once a shutdown has begun, so that it stops accepting new clients.
+///
+#[derive(Debug, Default)]
+pub struct ShutdownState {
+    begun: AtomicBool,
+}
+
+impl ShutdownState {
+    pub fn begin(&self) {
+        self.begun.store(true, Ordering::SeqCst);
+    }
+
+    pub fn has_begun(&self) -> bool {
+        self.begun.load(Ordering::SeqCst)
+    }
+}
+
+///
+/// Waits for SIGINT or SIGTERM, then asks the server to shut down gracefully
+/// (giving rounds in progress up to `timeout` to finish).
+/// A second signal asks the server to shut down immediately.
+///
+pub async fn handle_shutdown_signals(
+    state: Arc<ShutdownState>,
+    mut server_tx: Sender<MessageIn>,
+    timeout: Duration,
+) {
+    let mut terminate = UnixSignal::new(UnixSignalKind::Terminate);
+
+    for timeout in [timeout, Duration::from_secs(0)] {
+        select! {
+            _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+            _ = terminate.recv() => info!("Received SIGTERM"),
+        }
+
+        if state.has_begun() {
+            warn!("Shutting down immediately");
+        }
+        state.begin();
+
+        if let Err(error) = server_tx
+            .send(MessageIn::shutdown(SHUTDOWN_REASON, timeout))
+            .await
+        {
+            error!("Failed to send shutdown to server: {}", error);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnixSignalKind {
+    Hangup,
+    Terminate,
+}
+
+///
+/// A stream of one kind of unix signal, which never yields on platforms without them.
+///
+pub struct UnixSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl UnixSignal {
+    pub fn new(kind: UnixSignalKind) -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let signal_kind = match kind {
+                UnixSignalKind::Hangup => SignalKind::hangup(),
+                UnixSignalKind::Terminate => SignalKind::terminate(),
+            };
+
+            Self {
+                signal: signal(signal_kind)
+                    .map_err(|error| warn!("Failed to listen for {:?}: {}", kind, error))
+                    .ok(),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = kind;
+            Self {}
+        }
+    }
+
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Some(signal) = &mut self.signal {
+                signal.recv().await;
+                return;
+            }
+        }
+
+        future::pending::<()>().await
+    }
+}
@@ -0,0 +1,347 @@
+use anyhow::{Context, Error, anyhow};
+use chrono::{DateTime, Duration as OldDuration, Utc};
+use rand_core::{OsRng, RngCore};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::config::Command;
+use crate::server::{
+    ARENA_MAX_PLAYERS, Arena, ArenaInput, ArenaLine, ArenaUpdate, DEFAULT_TICK_RATE, Direction,
+    Lightcycle, Player, PlayerColor, PlayerId, Replay,
+};
+
+/// Rounds which are still going after this long are counted as draws.
+const SIMULATION_MAX_ROUND_SECONDS: f64 = 600.0;
+/// How far ahead (in seconds of travel) a bot checks for obstacles.
+const BOT_LOOKAHEAD_SECONDS: f64 = 0.5;
+/// A bot with a clear path ahead turns anyway on roughly one in this many ticks.
+const BOT_RANDOM_TURN_CHANCE: u32 = 40;
+
+pub fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::VerifyReplay { path } => {
+            let frames = verify_replay(&path)?;
+            println!(
+                "Replay {:?} reproduced the same final state over {} frames",
+                path, frames
+            );
+        }
+        Command::Simulate {
+            rounds,
+            players,
+            speed,
+        } => {
+            println!("{}", simulate(rounds, players, speed)?);
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// Re-simulates a recorded replay from its initial state, feeding in only the inputs found in
+/// its frames (the turns players made, and the joins, leaves and pings the server recorded),
+/// and checks that the simulated arena matches the recorded one after every frame.
+///
+/// Returns the number of frames verified.
+///
+pub fn verify_replay(path: &Path) -> Result<usize, Error> {
+    let replay = Replay::read(path)?;
+    let delta_time = replay.header.tick_seconds;
+    if !(delta_time > 0.0 && delta_time.is_finite()) {
+        return Err(anyhow!("Replay {:?} has no tick length", path));
+    }
+
+    let mut recorded = replay.initial_state.clone();
+    let mut simulated = replay.initial_state.clone();
+
+    for (index, frame) in replay.frames.iter().enumerate() {
+        // frame timestamps are truncated to whole milliseconds, so round up to be sure
+        // that a frame recorded just after the countdown ended is simulated as such
+        let now =
+            replay.header.recorded_at + OldDuration::milliseconds(frame.milliseconds as i64 + 1);
+
+        for update in &frame.updates {
+            update.apply(&mut recorded);
+
+            // joins, leaves and pings are decided by the server rather than simulated
+            if let ArenaUpdate::AddPlayer(_, _)
+            | ArenaUpdate::RemovePlayer(_)
+            | ArenaUpdate::UpdatePlayerPing(_, _) = update
+            {
+                simulated.updates.push(update.clone());
+            }
+        }
+        // the tick isn't carried by any update, so it's recorded alongside them
+        recorded.tick = frame.tick;
+
+        for (player_id, turn) in &frame.turns {
+            simulated.process_input_at(now, *player_id, *turn);
+        }
+        simulated.update_at(now, delta_time);
+        simulated.clear_updates();
+
+        if simulated.tick != recorded.tick {
+            return Err(anyhow!(
+                "Replay {:?} diverged from its simulation at frame {} ({} ms): \
+                 simulated tick {} but recorded tick {}",
+                path,
+                index,
+                frame.milliseconds,
+                simulated.tick,
+                recorded.tick
+            ));
+        }
+        if serde_json::to_value(&simulated)? != serde_json::to_value(&recorded)? {
+            return Err(anyhow!(
+                "Replay {:?} diverged from its simulation at frame {} ({} ms)",
+                path,
+                index,
+                frame.milliseconds
+            ));
+        }
+    }
+
+    if simulated.winner != replay.header.winner {
+        return Err(anyhow!(
+            "Replay {:?} records a different winner than its simulation",
+            path
+        ));
+    }
+
+    Ok(replay.frames.len())
+}
+
+///
+/// Runs `rounds` rounds between `players` bots, and collects how often each spawnpoint wins.
+///
+pub fn simulate(
+    rounds: usize,
+    players: usize,
+    speed: Option<f64>,
+) -> Result<SimulationReport, Error> {
+    if !(2..=ARENA_MAX_PLAYERS).contains(&players) {
+        return Err(anyhow!(
+            "Simulations need between 2 and {} players",
+            ARENA_MAX_PLAYERS
+        ));
+    }
+    if speed.is_some_and(|speed| !speed.is_finite() || speed <= 0.0) {
+        return Err(anyhow!("Lightcycle speed must be a positive number"));
+    }
+
+    let mut report = SimulationReport {
+        rounds,
+        ..Default::default()
+    };
+
+    for round in 0..rounds {
+        let result = simulate_round(players, speed)
+            .with_context(|| anyhow!("Failed to simulate round {}", round))?;
+
+        report.total_seconds += result.seconds;
+        if result.winner.is_none() {
+            report.draws += 1;
+        }
+        for (player_id, spawnpoint) in result.spawnpoints {
+            let stats = report.spawnpoints.entry(spawnpoint).or_default();
+            stats.rounds += 1;
+            if result.winner == Some(player_id) {
+                stats.wins += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, Default)]
+pub struct SimulationReport {
+    pub rounds: usize,
+    pub draws: usize,
+    pub total_seconds: f64,
+    pub spawnpoints: BTreeMap<String, SpawnpointStats>,
+}
+
+#[derive(Debug, Default)]
+pub struct SpawnpointStats {
+    pub rounds: usize,
+    pub wins: usize,
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Rounds:               {}", self.rounds)?;
+        writeln!(f, "Draws:                {}", self.draws)?;
+        writeln!(
+            f,
+            "Average round length: {:.2}s",
+            self.total_seconds / self.rounds.max(1) as f64
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<24} {:>8} {:>8} {:>9}",
+            "Spawnpoint", "Rounds", "Wins", "Win rate"
+        )?;
+        for (spawnpoint, stats) in &self.spawnpoints {
+            writeln!(
+                f,
+                "{:<24} {:>8} {:>8} {:>8.1}%",
+                spawnpoint,
+                stats.rounds,
+                stats.wins,
+                100.0 * stats.wins as f64 / stats.rounds.max(1) as f64
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+struct RoundResult {
+    spawnpoints: Vec<(PlayerId, String)>,
+    winner: Option<PlayerId>,
+    seconds: f64,
+}
+
+fn simulate_round(players: usize, speed: Option<f64>) -> Result<RoundResult, Error> {
+    let delta_time = 1.0 / DEFAULT_TICK_RATE as f64;
+    let mut now = Utc::now();
+
+    let mut arena = Arena::with_name("simulation");
+    for index in 0..players {
+        arena.add_player(Player {
+            name: format!("BOT{}", index + 1),
+            color: PlayerColor::NAMED[index % PlayerColor::NAMED.len()],
+            ..Default::default()
+        });
+    }
+    arena.apply_updates();
+
+    let any_player_id = *arena
+        .players
+        .keys()
+        .next()
+        .ok_or_else(|| anyhow!("Arena has no players"))?;
+    arena.process_input_at(now, any_player_id, ArenaInput::Start);
+    arena.apply_updates();
+    arena.clear_updates();
+
+    if let Some(speed) = speed {
+        for lightcycle in arena.lightcycles.values_mut() {
+            lightcycle.speed = speed;
+        }
+    }
+
+    let spawnpoints = arena
+        .lightcycles
+        .iter()
+        .map(|(player_id, lightcycle)| {
+            let spawnpoint = format!(
+                "({}, {}) {:?}",
+                lightcycle.position.x, lightcycle.position.y, lightcycle.direction
+            );
+            (*player_id, spawnpoint)
+        })
+        .collect();
+
+    // skip the countdown
+    let started: DateTime<Utc> = arena
+        .started
+        .ok_or_else(|| anyhow!("Arena did not start"))?;
+    now = started;
+
+    let mut seconds = 0.0;
+    while arena.winner.is_none() && arena.started.is_some() {
+        if seconds >= SIMULATION_MAX_ROUND_SECONDS {
+            return Ok(RoundResult {
+                spawnpoints,
+                winner: None,
+                seconds,
+            });
+        }
+
+        let turns: Vec<(PlayerId, Direction)> = arena
+            .lightcycles
+            .iter()
+            .filter(|(_, lightcycle)| !lightcycle.dead)
+            .filter_map(|(player_id, lightcycle)| {
+                Some((*player_id, choose_direction(&arena, lightcycle)?))
+            })
+            .collect();
+        for (player_id, direction) in turns {
+            arena.process_input_at(now, player_id, ArenaInput::Turn(direction));
+        }
+
+        arena.update_at(now, delta_time);
+        arena.clear_updates();
+
+        seconds += delta_time;
+        // measured from the start (rather than tick by tick) so that rounding doesn't add up
+        now = started + OldDuration::microseconds((seconds * 1_000_000.0) as i64);
+    }
+
+    Ok(RoundResult {
+        spawnpoints,
+        winner: arena.winner,
+        seconds,
+    })
+}
+
+///
+/// A simple bot: keeps going straight until something is in the way (or on a whim),
+/// then turns towards a random clear direction.
+///
+fn choose_direction(arena: &Arena, lightcycle: &Lightcycle) -> Option<Direction> {
+    let ahead_is_clear = is_clear(arena, lightcycle, lightcycle.direction);
+    if ahead_is_clear && !OsRng.next_u32().is_multiple_of(BOT_RANDOM_TURN_CHANCE) {
+        return None;
+    }
+
+    let options: Vec<Direction> = [
+        Direction::Up,
+        Direction::Left,
+        Direction::Right,
+        Direction::Down,
+    ]
+    .iter()
+    .copied()
+    .filter(|direction| {
+        *direction != lightcycle.direction && !direction.is_opposite(lightcycle.direction)
+    })
+    .filter(|direction| is_clear(arena, lightcycle, *direction))
+    .collect();
+
+    if options.is_empty() {
+        return None;
+    }
+
+    Some(options[OsRng.next_u32() as usize % options.len()])
+}
+
+fn is_clear(arena: &Arena, lightcycle: &Lightcycle, direction: Direction) -> bool {
+    let ahead =
+        lightcycle.position + direction.as_velocity() * lightcycle.speed * BOT_LOOKAHEAD_SECONDS;
+
+    if ahead.x < 0.0 || ahead.y < 0.0 || ahead.x > arena.width || ahead.y > arena.height {
+        return false;
+    }
+
+    let path = ArenaLine {
+        from: lightcycle.position.to_untyped(),
+        to: ahead.to_untyped(),
+    };
+
+    arena.lightribbons.values().all(|lightribbon| {
+        lightribbon.points.windows(2).all(|line| {
+            let line = ArenaLine {
+                from: line[0].to_untyped(),
+                to: line[1].to_untyped(),
+            };
+
+            !path.overlaps_segment(&line) && path.intersection(&line).is_none()
+        })
+    })
+}
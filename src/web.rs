@@ -1,57 +1,173 @@
+mod admin;
+mod api;
+mod connections;
+mod directory;
 mod embed;
 mod errors;
+mod health;
+mod invite;
+mod metrics;
 mod proxy;
+mod rate_limit;
+mod replays;
+mod tls;
 mod websocket;
 
+use anyhow::{Context, Error, anyhow};
+use futures::future;
+use log::info;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 use warp::{Filter, Reply};
 
-use crate::config::Config;
-use crate::server::MessageIn;
+use crate::config::{Config, FrontendSource};
+use crate::metrics::Metrics;
+use crate::server::{BanList, Heartbeat, MessageIn};
+use crate::signals::ShutdownState;
+use admin::admin;
+use api::api;
+use connections::{ConnectionLimiter, admit};
+use directory::directory;
 use embed::embed;
-use errors::handle_rejection;
+use errors::{ServiceUnavailable, handle_refusal, handle_rejection};
+use health::health;
 use proxy::proxy;
+use replays::replays;
 use websocket::websocket;
 
-#[cfg(debug_assertions)]
-const DEBUG: bool = true;
-#[cfg(not(debug_assertions))]
-const DEBUG: bool = false;
+pub async fn start(
+    server_tx: Sender<MessageIn>,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    heartbeat: Arc<Heartbeat>,
+    bans: Arc<BanList>,
+    shutdown: Arc<ShutdownState>,
+) -> Result<(), Error> {
+    //
+    // json api handler
+    //
+    let api = api(server_tx.clone());
+
+    //
+    // admin api handler
+    //
+    let admin = admin(server_tx.clone(), config.admin_token.clone());
 
-pub async fn start(server_tx: Sender<MessageIn>, config: Arc<Config>) {
     let server_tx = warp::any().map(move || server_tx.clone());
+    let websocket_metrics = {
+        let metrics = metrics.clone();
+        warp::any().map(move || metrics.clone())
+    };
+
+    //
+    // health and readiness handlers
+    //
+    let health = health(heartbeat, shutdown.clone());
+
+    //
+    // new clients are turned away once the server has begun shutting down
+    //
+    let accepting_clients = warp::any()
+        .and_then(move || {
+            let result = match shutdown.has_begun() {
+                true => Err(warp::reject::custom(ServiceUnavailable)),
+                false => Ok(()),
+            };
+            future::ready(result)
+        })
+        .untuple_one();
+
+    //
+    // banned addresses, and those with too many connections open, are turned away
+    //
+    let admit = admit(
+        Arc::new(config.trusted_proxies.clone()),
+        bans,
+        Arc::new(ConnectionLimiter::new(config.max_connections_per_ip)),
+    );
 
     //
     // websocket handler
+    // refusals are answered here, rather than falling through to the frontend
     //
     let ws = warp::path("ws")
         .and(warp::path::end())
+        .and(accepting_clients)
         .and(warp::ws())
-        .and(warp::addr::remote())
+        .and(admit)
         .and(server_tx)
-        .map(websocket);
+        .and(websocket_metrics)
+        .map(websocket)
+        .recover(handle_refusal);
 
     //
-    // proxy handler
+    // prometheus metrics handler
     //
-    let proxy = proxy("http://localhost:3001");
+    let metrics = metrics::metrics(metrics);
 
     //
-    // fs handler
+    // replay list handler
     //
-    let embed = embed();
+    let replays = replays(
+        config
+            .replay_directory
+            .clone()
+            .filter(|_| config.features.replays),
+    );
 
     //
-    // use proxy in development
-    // use fs (binary-embedded) in release
+    // frontend handler
+    // serves the client from a directory on disk, from the client dev server (proxy)
+    // or from fs (binary-embedded), as configured
     //
-    let frontend = if DEBUG {
-        proxy.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed()
-    } else {
-        embed.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed()
+    let frontend = match (config.frontend_source, config.static_directory.clone()) {
+        (FrontendSource::Directory, Some(static_directory)) => directory(static_directory)
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
+        (FrontendSource::Proxy, _) => proxy(&config.proxy_target)
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
+        _ => embed()
+            .map(|reply| Box::new(reply) as Box<dyn Reply>)
+            .boxed(),
     };
+    info!("Serving the client from {:?}", config.frontend_source);
+
+    let routes = ws
+        .or(health)
+        .or(metrics)
+        .or(admin)
+        .or(api)
+        .or(replays)
+        .or(frontend)
+        .recover(handle_rejection);
+
+    //
+    // serve over https if a certificate is configured
+    // optionally redirecting plain http requests to https
+    //
+    match (config.tls_certificate.clone(), config.tls_key.clone()) {
+        (Some(certificate), Some(key)) => {
+            if let Some(http_redirect_port) = config.http_redirect_port {
+                let redirect_address =
+                    SocketAddr::new(config.bind_address.ip(), http_redirect_port);
+                // bound up front, so that a port which can't be bound stops startup
+                let (redirect_address, redirect) =
+                    warp::serve(tls::redirect_to_https(config.bind_address.port()))
+                        .try_bind_ephemeral(redirect_address)
+                        .with_context(|| {
+                            anyhow!("Failed to bind the http redirect to {}", redirect_address)
+                        })?;
+                tokio::spawn(redirect);
+                info!("Redirecting http://{} to https", redirect_address);
+            }
 
-    let routes = ws.or(frontend).recover(handle_rejection);
-    warp::serve(routes).bind(config.bind_address).await;
+            tls::serve(routes, config.bind_address, certificate, key).await
+        }
+        _ => {
+            warp::serve(routes).bind(config.bind_address).await;
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,264 @@
+use bytes::Bytes;
+use futures::future;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::server::{
+    AdminCommand, AdminError, AdminReply, ArenaId, ClientId, MessageIn, RuntimeSettings,
+};
+use crate::web::api::ask;
+use crate::web::errors::{BadRequest, Unauthorized, handle_refusal};
+
+/// Admin requests with larger bodies than this are refused.
+const ADMIN_BODY_MAX_BYTES: u64 = 16 * 1024;
+const KICK_REASON: &str = "You were kicked by an administrator";
+const BAN_REASON: &str = "Banned by an administrator";
+const CLOSE_ARENA_REASON: &str = "The arena was closed by an administrator";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KickRequest {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BanRequest {
+    reason: Option<String>,
+    /// The ban lasts until the server restarts if unset.
+    duration_seconds: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BanIpAddressRequest {
+    ip_address: IpAddr,
+    reason: Option<String>,
+    /// The ban lasts until the server restarts if unset.
+    duration_seconds: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CloseArenaRequest {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnnouncementRequest {
+    text: String,
+}
+
+///
+/// Routes for the server's operators, which require the configured admin token
+/// as a bearer token in the `Authorization` header.
+///
+/// The routes aren't served at all when no admin token is configured.
+///
+pub fn admin(
+    server_tx: Sender<MessageIn>,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let server_tx = warp::any().map(move || server_tx.clone());
+
+    let list_clients = warp::path!("clients")
+        .and(warp::get())
+        .and(server_tx.clone())
+        .and_then(|server_tx| send(server_tx, AdminCommand::ListClients));
+
+    let kick = warp::path!("clients" / ClientId / "kick")
+        .and(warp::post())
+        .and(json_body())
+        .and(server_tx.clone())
+        .and_then(|client_id, request: KickRequest, server_tx| {
+            send(server_tx, move |tx| AdminCommand::Kick {
+                client_id,
+                reason: request.reason.unwrap_or_else(|| KICK_REASON.to_owned()),
+                tx,
+            })
+        });
+
+    let ban = warp::path!("clients" / ClientId / "ban")
+        .and(warp::post())
+        .and(json_body())
+        .and(server_tx.clone())
+        .and_then(|client_id, request: BanRequest, server_tx| {
+            send(server_tx, move |tx| AdminCommand::Ban {
+                client_id,
+                reason: request.reason.unwrap_or_else(|| BAN_REASON.to_owned()),
+                duration: request.duration_seconds.map(Duration::from_secs),
+                tx,
+            })
+        });
+
+    let list_bans = warp::path!("bans")
+        .and(warp::get())
+        .and(server_tx.clone())
+        .and_then(|server_tx| send(server_tx, AdminCommand::ListBans));
+
+    let ban_ip_address = warp::path!("bans")
+        .and(warp::post())
+        .and(json_body())
+        .and(server_tx.clone())
+        .and_then(|request: BanIpAddressRequest, server_tx| {
+            send(server_tx, move |tx| AdminCommand::BanIpAddress {
+                ip_address: request.ip_address,
+                reason: request.reason.unwrap_or_else(|| BAN_REASON.to_owned()),
+                duration: request.duration_seconds.map(Duration::from_secs),
+                tx,
+            })
+        });
+
+    let unban = warp::path!("bans" / IpAddr)
+        .and(warp::delete())
+        .and(server_tx.clone())
+        .and_then(|ip_address, server_tx| {
+            send(server_tx, move |tx| AdminCommand::Unban { ip_address, tx })
+        });
+
+    let close_arena = warp::path!("arenas" / ArenaId / "close")
+        .and(warp::post())
+        .and(json_body())
+        .and(server_tx.clone())
+        .and_then(|arena_id, request: CloseArenaRequest, server_tx| {
+            send(server_tx, move |tx| AdminCommand::CloseArena {
+                arena_id,
+                reason: request
+                    .reason
+                    .unwrap_or_else(|| CLOSE_ARENA_REASON.to_owned()),
+                tx,
+            })
+        });
+
+    let announce = warp::path!("announcements")
+        .and(warp::post())
+        .and(json_body())
+        .and(server_tx.clone())
+        .and_then(|request: AnnouncementRequest, server_tx| {
+            send(server_tx, move |tx| AdminCommand::Announce {
+                text: request.text,
+                tx,
+            })
+        });
+
+    let get_settings = warp::path!("settings")
+        .and(warp::get())
+        .and(server_tx.clone())
+        .and_then(|server_tx| send(server_tx, AdminCommand::GetSettings));
+
+    let set_settings = warp::path!("settings")
+        .and(warp::put())
+        .and(json_body())
+        .and(server_tx)
+        .and_then(|settings: RuntimeSettings, server_tx| {
+            send(server_tx, move |tx| AdminCommand::SetSettings {
+                settings,
+                tx,
+            })
+        });
+
+    warp::path("api")
+        .and(warp::path("admin"))
+        .and(authorized(admin_token))
+        .and(
+            list_clients
+                .or(kick)
+                .or(ban)
+                .or(list_bans)
+                .or(ban_ip_address)
+                .or(unban)
+                .or(close_arena)
+                .or(announce)
+                .or(get_settings)
+                .or(set_settings),
+        )
+        // answered here, so that these requests don't fall through to the client
+        .recover(handle_refusal)
+}
+
+///
+/// Rejects requests which don't carry the admin token.
+///
+fn authorized(admin_token: Option<String>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let result = match &admin_token {
+                None => Err(warp::reject::not_found()),
+                Some(admin_token) => match authorization
+                    .as_deref()
+                    .and_then(|authorization| authorization.strip_prefix("Bearer "))
+                {
+                    Some(token) if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) => {
+                        Ok(())
+                    }
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                },
+            };
+            future::ready(result)
+        })
+        .untuple_one()
+}
+
+/// Compares two byte strings without exiting early, so that the comparison takes as long
+/// however much of a guessed token is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+///
+/// Parses a json request body, where an empty body stands for an empty object.
+///
+fn json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = Rejection> + Copy
+{
+    // unlike warp::body::content_length_limit, this allows requests without a body
+    // (and so without a content length)
+    warp::header::optional::<u64>("content-length")
+        .and_then(|content_length: Option<u64>| {
+            future::ready(match content_length {
+                Some(content_length) if content_length > ADMIN_BODY_MAX_BYTES => Err(
+                    warp::reject::custom(BadRequest("Request body too large".to_owned())),
+                ),
+                _ => Ok(()),
+            })
+        })
+        .untuple_one()
+        .and(warp::body::bytes())
+        .and_then(|body: Bytes| {
+            let body: &[u8] = match body.is_empty() {
+                true => b"{}",
+                false => &body,
+            };
+            future::ready(
+                serde_json::from_slice(body)
+                    .map_err(|error| warp::reject::custom(BadRequest(error.to_string()))),
+            )
+        })
+}
+
+///
+/// Sends a command to the server, and replies with its outcome as json.
+///
+async fn send<T: Serialize>(
+    server_tx: Sender<MessageIn>,
+    command: impl FnOnce(AdminReply<T>) -> AdminCommand,
+) -> Result<impl Reply, Rejection> {
+    let result = ask(server_tx, |tx| MessageIn::admin(command(tx))).await?;
+
+    Ok(match result {
+        Ok(value) => warp::reply::with_status(warp::reply::json(&value), StatusCode::OK),
+        Err(error) => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": error.to_string() })),
+            match error {
+                AdminError::NotFound(_) => StatusCode::NOT_FOUND,
+                AdminError::Invalid(_) => StatusCode::BAD_REQUEST,
+            },
+        ),
+    })
+}
@@ -0,0 +1,101 @@
+use log::error;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::time;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::server::{ArenaId, MessageIn, ServerQuery};
+use crate::web::errors::{InternalServerError, ServiceUnavailable};
+
+/// How long to wait for the server to reply before giving up.
+const REPLY_TIMEOUT_SECONDS: u64 = 5;
+
+///
+/// Read-only json routes describing the server's arenas and status,
+/// answered by the server task itself.
+///
+pub fn api(
+    server_tx: Sender<MessageIn>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let server_tx = warp::any().map(move || server_tx.clone());
+
+    let arenas = warp::path!("api" / "arenas")
+        .and(warp::get())
+        .and(server_tx.clone())
+        .and_then(serve_arena_list);
+
+    let arena = warp::path!("api" / "arenas" / ArenaId)
+        .and(warp::get())
+        .and(server_tx.clone())
+        .and_then(serve_arena);
+
+    let status = warp::path!("api" / "status")
+        .and(warp::get())
+        .and(server_tx)
+        .and_then(serve_status);
+
+    arenas.or(arena).or(status)
+}
+
+async fn serve_arena_list(server_tx: Sender<MessageIn>) -> Result<impl Reply, Rejection> {
+    let arenas = ask(server_tx, |tx| MessageIn::query(ServerQuery::ArenaList(tx))).await?;
+
+    Ok(warp::reply::json(&arenas))
+}
+
+async fn serve_arena(
+    arena_id: ArenaId,
+    server_tx: Sender<MessageIn>,
+) -> Result<impl Reply, Rejection> {
+    let arena = ask(server_tx, |tx| {
+        MessageIn::query(ServerQuery::Arena(arena_id, tx))
+    })
+    .await?;
+
+    // answered here rather than rejected, so that the request doesn't fall through to the client
+    Ok(match arena {
+        Some(arena) => warp::reply::with_status(warp::reply::json(&arena), StatusCode::OK),
+        None => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": "Arena not found" })),
+            StatusCode::NOT_FOUND,
+        ),
+    })
+}
+
+async fn serve_status(server_tx: Sender<MessageIn>) -> Result<impl Reply, Rejection> {
+    let status = ask(server_tx, |tx| MessageIn::query(ServerQuery::Status(tx))).await?;
+
+    Ok(warp::reply::json(&status))
+}
+
+///
+/// Sends a message carrying a reply channel to the server, and waits for its reply.
+///
+pub async fn ask<T>(
+    mut server_tx: Sender<MessageIn>,
+    message: impl FnOnce(oneshot::Sender<T>) -> MessageIn,
+) -> Result<T, Rejection> {
+    let (tx, rx) = oneshot::channel();
+
+    server_tx.send(message(tx)).await.map_err(|error| {
+        error!("Failed to ask server: {}", error);
+        warp::reject::custom(ServiceUnavailable)
+    })?;
+
+    match time::timeout(Duration::from_secs(REPLY_TIMEOUT_SECONDS), rx).await {
+        Ok(Ok(answer)) => Ok(answer),
+        Ok(Err(error)) => {
+            error!("Server dropped request without replying: {}", error);
+            Err(warp::reject::custom(InternalServerError))
+        }
+        Err(_) => {
+            error!(
+                "Server didn't reply within {} seconds",
+                REPLY_TIMEOUT_SECONDS
+            );
+            Err(warp::reject::custom(ServiceUnavailable))
+        }
+    }
+}
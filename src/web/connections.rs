@@ -0,0 +1,224 @@
+use futures::future;
+use log::info;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use warp::{Filter, Rejection};
+
+use crate::server::BanList;
+use crate::web::errors::{Forbidden, TooManyRequests};
+use crate::web::tls;
+
+///
+/// Counts the websocket connections open from each ip address,
+/// and refuses more than the configured maximum.
+///
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    max_per_ip: Option<usize>,
+    connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+///
+/// Holds one of an ip address' connections open, until it's dropped.
+///
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip_address: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: Option<usize>) -> Self {
+        Self {
+            max_per_ip,
+            connections: Default::default(),
+        }
+    }
+
+    fn acquire(self: &Arc<Self>, ip_address: IpAddr) -> Option<ConnectionPermit> {
+        let mut connections = self.lock();
+        let count = connections.entry(ip_address).or_default();
+        if self
+            .max_per_ip
+            .is_some_and(|max_per_ip| *count >= max_per_ip)
+        {
+            return None;
+        }
+        *count += 1;
+
+        Some(ConnectionPermit {
+            limiter: self.clone(),
+            ip_address,
+        })
+    }
+
+    // the counts are left consistent by every method, so they're still usable if a holder panicked
+    fn lock(&self) -> MutexGuard<'_, HashMap<IpAddr, usize>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut connections = self.limiter.lock();
+        if let Some(count) = connections.get_mut(&self.ip_address) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                connections.remove(&self.ip_address);
+            }
+        }
+    }
+}
+
+///
+/// Works out the address of the client making the request, turns it away if it's banned
+/// or already has too many connections open, and otherwise hands out a permit
+/// for the new connection.
+///
+pub fn admit(
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    bans: Arc<BanList>,
+    limiter: Arc<ConnectionLimiter>,
+) -> impl Filter<Extract = (Option<IpAddr>, Option<ConnectionPermit>), Error = Rejection> + Clone {
+    tls::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and_then(
+            move |remote_addr: Option<SocketAddr>, forwarded_for: Option<String>| {
+                let ip_address = remote_addr.map(|remote_addr| {
+                    client_ip_address(remote_addr.ip(), forwarded_for.as_deref(), &trusted_proxies)
+                });
+
+                let result = match ip_address {
+                    // connections without an address (which warp always provides) can't be checked
+                    None => Ok((None, None)),
+                    Some(ip_address) => match bans.get(ip_address) {
+                        Some(ban) => {
+                            info!(
+                                "Refusing connection from banned {}: {}",
+                                ip_address, ban.reason
+                            );
+                            Err(warp::reject::custom(Forbidden))
+                        }
+                        None => match limiter.acquire(ip_address) {
+                            Some(permit) => Ok((Some(ip_address), Some(permit))),
+                            None => {
+                                info!(
+                                    "Refusing connection from {}: too many connections",
+                                    ip_address
+                                );
+                                Err(warp::reject::custom(TooManyRequests))
+                            }
+                        },
+                    },
+                };
+                future::ready(result)
+            },
+        )
+        .untuple_one()
+}
+
+///
+/// Requests from trusted proxies carry the address they were forwarded for in the
+/// X-Forwarded-For header, where each proxy along the way appends the address it received the
+/// request from. Reading from the right, the first address which isn't a trusted proxy is the
+/// client's (anything further left could have been made up by the client).
+///
+fn client_ip_address(
+    remote_ip_address: IpAddr,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> IpAddr {
+    let mut ip_address = remote_ip_address;
+    let forwarded_for = match forwarded_for {
+        Some(forwarded_for) if trusted_proxies.contains(&remote_ip_address) => forwarded_for,
+        _ => return ip_address,
+    };
+
+    for forwarded_ip_address in forwarded_for.rsplit(',') {
+        match forwarded_ip_address.trim().parse::<IpAddr>() {
+            Ok(forwarded_ip_address) => ip_address = forwarded_ip_address,
+            Err(_) => break,
+        }
+        if !trusted_proxies.contains(&ip_address) {
+            break;
+        }
+    }
+
+    ip_address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXY: [u8; 4] = [10, 0, 0, 1];
+    const INNER_PROXY: [u8; 4] = [10, 0, 0, 2];
+    const CLIENT: [u8; 4] = [203, 0, 113, 7];
+
+    fn client_ip(remote: [u8; 4], forwarded_for: Option<&str>) -> IpAddr {
+        let trusted_proxies = [IpAddr::from(PROXY), IpAddr::from(INNER_PROXY)];
+        client_ip_address(IpAddr::from(remote), forwarded_for, &trusted_proxies)
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_addresses() {
+        assert_eq!(
+            client_ip(CLIENT, Some("198.51.100.1")),
+            IpAddr::from(CLIENT)
+        );
+    }
+
+    #[test]
+    fn remote_address_is_used_without_forwarded_for() {
+        assert_eq!(client_ip(PROXY, None), IpAddr::from(PROXY));
+    }
+
+    #[test]
+    fn forwarded_for_is_read_from_trusted_proxies() {
+        assert_eq!(client_ip(PROXY, Some("203.0.113.7")), IpAddr::from(CLIENT));
+        assert_eq!(
+            client_ip(PROXY, Some("2001:db8::1")),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn chains_of_trusted_proxies_are_walked() {
+        assert_eq!(
+            client_ip(PROXY, Some("203.0.113.7, 10.0.0.2")),
+            IpAddr::from(CLIENT)
+        );
+    }
+
+    #[test]
+    fn addresses_left_of_the_client_are_ignored() {
+        // anything left of the first untrusted address could have been sent by the client
+        assert_eq!(
+            client_ip(PROXY, Some("198.51.100.1, 203.0.113.7")),
+            IpAddr::from(CLIENT)
+        );
+        assert_eq!(
+            client_ip(PROXY, Some("10.0.0.2, 203.0.113.7, 10.0.0.2")),
+            IpAddr::from(CLIENT)
+        );
+    }
+
+    #[test]
+    fn unparsable_entries_stop_the_walk() {
+        assert_eq!(
+            client_ip(PROXY, Some("203.0.113.7, not-an-address")),
+            IpAddr::from(PROXY)
+        );
+        assert_eq!(
+            client_ip(PROXY, Some("not-an-address, 203.0.113.7")),
+            IpAddr::from(CLIENT)
+        );
+        assert_eq!(
+            client_ip(PROXY, Some("203.0.113.7, 10.0.0.2, ")),
+            IpAddr::from(PROXY)
+        );
+    }
+}
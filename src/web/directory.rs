@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+use warp::{Filter, Rejection, Reply};
+
+use crate::web::invite::invite;
+
+pub fn directory(
+    path: PathBuf,
+) -> impl Filter<Extract = (impl Reply + use<>,), Error = Rejection> + Clone + use<> {
+    let invite = invite().and(warp::fs::file(path.join("index.html")));
+    let path = warp::fs::dir(path);
+
+    invite.or(path)
+}
@@ -5,13 +5,14 @@ use warp::reply::Response;
 use warp::{Filter, Rejection, Reply};
 
 use crate::web::errors::InternalServerError;
+use crate::web::invite::invite;
 
 #[derive(RustEmbed)]
 #[folder = "$CARGO_MANIFEST_DIR/client/dist"]
 struct Asset;
 
 pub fn embed() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    let index = warp::path::end().and_then(serve_index);
+    let index = warp::path::end().or(invite()).unify().and_then(serve_index);
     let path = warp::path::tail().and_then(serve_path);
 
     index.or(path)
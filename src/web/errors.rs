@@ -1,3 +1,4 @@
+use log::debug;
 use warp::http::StatusCode;
 use warp::reject::Reject;
 use warp::{Rejection, Reply};
@@ -6,10 +7,48 @@ use warp::{Rejection, Reply};
 pub struct InternalServerError;
 impl Reject for InternalServerError {}
 
+#[derive(Debug)]
+pub struct BadRequest(pub String);
+impl Reject for BadRequest {}
+
+#[derive(Debug)]
+pub struct Unauthorized;
+impl Reject for Unauthorized {}
+
+#[derive(Debug)]
+pub struct Forbidden;
+impl Reject for Forbidden {}
+
+#[derive(Debug)]
+pub struct TooManyRequests;
+impl Reject for TooManyRequests {}
+
 #[derive(Debug)]
 pub struct BadGateway;
 impl Reject for BadGateway {}
 
+#[derive(Debug)]
+pub struct ServiceUnavailable;
+impl Reject for ServiceUnavailable {}
+
+///
+/// Answers the rejections raised by routes which matched the request but refused it,
+/// and passes on any others (such as not found), so that the request can fall through
+/// to the routes after them.
+///
+pub async fn handle_refusal(error: Rejection) -> Result<impl Reply, Rejection> {
+    let refused = error.find::<BadRequest>().is_some()
+        || error.find::<Unauthorized>().is_some()
+        || error.find::<Forbidden>().is_some()
+        || error.find::<TooManyRequests>().is_some()
+        || error.find::<ServiceUnavailable>().is_some();
+
+    match refused {
+        true => handle_rejection(error).await,
+        false => Err(error),
+    }
+}
+
 pub async fn handle_rejection(error: Rejection) -> Result<impl Reply, Rejection> {
     if error.is_not_found() {
         return Ok(warp::reply::with_status("Not Found", StatusCode::NOT_FOUND));
@@ -22,6 +61,32 @@ pub async fn handle_rejection(error: Rejection) -> Result<impl Reply, Rejection>
         ));
     }
 
+    if let Some(BadRequest(message)) = error.find() {
+        debug!("Bad request: {}", message);
+        return Ok(warp::reply::with_status(
+            "Bad Request",
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    if let Some(Unauthorized) = error.find() {
+        return Ok(warp::reply::with_status(
+            "Unauthorized",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+
+    if let Some(Forbidden) = error.find() {
+        return Ok(warp::reply::with_status("Forbidden", StatusCode::FORBIDDEN));
+    }
+
+    if let Some(TooManyRequests) = error.find() {
+        return Ok(warp::reply::with_status(
+            "Too Many Requests",
+            StatusCode::TOO_MANY_REQUESTS,
+        ));
+    }
+
     if let Some(BadGateway) = error.find() {
         return Ok(warp::reply::with_status(
             "Bad Gateway",
@@ -29,5 +94,12 @@ pub async fn handle_rejection(error: Rejection) -> Result<impl Reply, Rejection>
         ));
     }
 
+    if let Some(ServiceUnavailable) = error.find() {
+        return Ok(warp::reply::with_status(
+            "Service Unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+
     Err(error)
 }
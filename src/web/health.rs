@@ -0,0 +1,36 @@
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use crate::server::Heartbeat;
+use crate::signals::ShutdownState;
+
+///
+/// `/healthz` answers whenever the process is able to serve http at all.
+///
+/// `/readyz` answers only while the server loop is ticking on time,
+/// and stops once the server has begun shutting down (so that load balancers stop sending
+/// new players its way).
+///
+pub fn health(
+    heartbeat: Arc<Heartbeat>,
+    shutdown: Arc<ShutdownState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let healthz = warp::path!("healthz")
+        .and(warp::get())
+        .map(|| warp::reply::with_status("OK", StatusCode::OK));
+
+    let readyz = warp::path!("readyz").and(warp::get()).map(move || {
+        match (heartbeat.is_healthy(), shutdown.has_begun()) {
+            (true, false) => warp::reply::with_status("OK", StatusCode::OK),
+            (false, _) => {
+                warp::reply::with_status("Server is not ticking", StatusCode::SERVICE_UNAVAILABLE)
+            }
+            (true, true) => {
+                warp::reply::with_status("Server is shutting down", StatusCode::SERVICE_UNAVAILABLE)
+            }
+        }
+    });
+
+    healthz.or(readyz)
+}
@@ -0,0 +1,11 @@
+use warp::{Filter, Rejection};
+
+///
+/// Matches invite links (`/a/{code}`), which are answered with the client's index page.
+/// The client reads the arena code from the url itself.
+///
+pub fn invite() -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::path!("a" / String)
+        .map(|_arena_code: String| ())
+        .untuple_one()
+}
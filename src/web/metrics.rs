@@ -0,0 +1,31 @@
+use log::error;
+use std::sync::Arc;
+use warp::{Filter, Rejection, Reply};
+
+use crate::get_error_chain;
+use crate::metrics::Metrics;
+use crate::web::errors::InternalServerError;
+
+pub fn metrics(
+    metrics: Arc<Metrics>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let metrics = warp::any().map(move || metrics.clone());
+
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(metrics)
+        .and_then(serve_metrics)
+}
+
+async fn serve_metrics(metrics: Arc<Metrics>) -> Result<impl Reply, Rejection> {
+    let metrics = metrics.encode().map_err(|error| {
+        error!("Failed to serve metrics: {}", get_error_chain(error));
+        warp::reject::custom(InternalServerError)
+    })?;
+
+    Ok(warp::reply::with_header(
+        metrics,
+        "content-type",
+        prometheus::TEXT_FORMAT,
+    ))
+}
@@ -14,6 +14,7 @@ use warp::ws::{Message as WarpMessage, Ws};
 use warp::{Filter, Rejection, Reply};
 
 use crate::web::errors::{BadGateway, InternalServerError};
+use crate::web::tls::remote;
 
 const X_FORWARDED_FOR: &str = "x-forwarded-for";
 const HOP_BY_HOP_HEADERS: &[&str] = &[
@@ -45,7 +46,7 @@ pub fn proxy(
         .and(warp::path::tail())
         .and(warp::query::raw().or(empty_query).unify())
         .and(warp::header::headers_cloned())
-        .and(warp::addr::remote())
+        .and(remote())
         .and(target.clone())
         .and_then(proxy_ws);
 
@@ -54,7 +55,7 @@ pub fn proxy(
         .and(warp::body::stream())
         .and(warp::header::headers_cloned())
         .and(warp::method())
-        .and(warp::addr::remote())
+        .and(remote())
         .and(target)
         .and_then(proxy_http);
 
@@ -234,23 +235,23 @@ fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
     }
 }
 fn append_x_forwarded_for_header(remote_addr: Option<SocketAddr>, headers: &mut HeaderMap) {
-    if let Some(remote_addr) = remote_addr {
-        if let Ok(remote_addr) = HeaderValue::from_str(&remote_addr.ip().to_string()) {
-            match headers.entry(X_FORWARDED_FOR) {
-                Entry::Vacant(entry) => {
-                    entry.insert(remote_addr);
-                }
-                Entry::Occupied(mut entry) => {
-                    let existing = entry.get_mut();
-                    match HeaderValue::from_bytes(
-                        &[existing.as_bytes(), b", ", remote_addr.as_bytes()].concat(),
-                    ) {
-                        Ok(updated) => {
-                            *existing = updated;
-                        }
-                        _ => {
-                            *existing = remote_addr;
-                        }
+    if let Some(remote_addr) = remote_addr
+        && let Ok(remote_addr) = HeaderValue::from_str(&remote_addr.ip().to_string())
+    {
+        match headers.entry(X_FORWARDED_FOR) {
+            Entry::Vacant(entry) => {
+                entry.insert(remote_addr);
+            }
+            Entry::Occupied(mut entry) => {
+                let existing = entry.get_mut();
+                match HeaderValue::from_bytes(
+                    &[existing.as_bytes(), b", ", remote_addr.as_bytes()].concat(),
+                ) {
+                    Ok(updated) => {
+                        *existing = updated;
+                    }
+                    _ => {
+                        *existing = remote_addr;
                     }
                 }
             }
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Clients whose messages keep being dropped can run up this many strikes before they're
+/// disconnected.
+const MAX_STRIKES: f64 = 20.0;
+/// Strikes wear off at this rate while a client keeps to its limits.
+const STRIKES_FORGIVEN_PER_SECOND: f64 = 1.0;
+
+///
+/// How many messages of a type can arrive at once, and how quickly that allowance refills.
+///
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    burst: f64,
+    per_second: f64,
+}
+
+impl Limit {
+    const fn new(burst: f64, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+
+    fn for_message(name: &str) -> Self {
+        match name {
+            // steering needs to keep up with the player, so it gets plenty of room
+            "Turn" => Limit::new(30.0, 20.0),
+            "ReplayControl" => Limit::new(10.0, 5.0),
+            // the client polls this every couple of seconds
+            "GetArenaList" => Limit::new(5.0, 1.0),
+            "Chat" => Limit::new(5.0, 1.0),
+            "Join" | "JoinByCode" | "QuickMatch" | "LeaveQuickMatch" | "Start" | "WatchReplay" => {
+                Limit::new(5.0, 0.5)
+            }
+            "DesyncDetected" => Limit::new(3.0, 0.2),
+            _ => Limit::new(10.0, 5.0),
+        }
+    }
+}
+
+///
+/// A bucket which holds up to `burst` tokens, refilling continuously at `per_second`.
+///
+#[derive(Debug)]
+struct TokenBucket {
+    limit: Limit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: Limit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst);
+        self.refilled_at = now;
+    }
+
+    /// Takes a token if there's one left.
+    fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// What to do with a message from a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// The client is over its limit for this type of message.
+    Drop,
+    /// The client has gone over its limits too often, and should be disconnected.
+    Disconnect,
+}
+
+///
+/// Limits how often one client can send each type of message, so that a single client
+/// can't flood the server's queue.
+///
+/// Messages over the limit are dropped, and each one dropped is a strike against the client.
+/// Strikes wear off over time, but a client which runs up too many is disconnected.
+///
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    /// Counted down from full by each strike, so that strikes wear off as it refills.
+    strikes: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+
+    fn new_at(now: Instant) -> Self {
+        Self {
+            buckets: HashMap::new(),
+            strikes: TokenBucket::new(Limit::new(MAX_STRIKES, STRIKES_FORGIVEN_PER_SECOND), now),
+        }
+    }
+
+    ///
+    /// Checks a message of the given type against the client's limits.
+    ///
+    pub fn check(&mut self, name: &'static str) -> Verdict {
+        self.check_at(name, Instant::now())
+    }
+
+    fn check_at(&mut self, name: &'static str, now: Instant) -> Verdict {
+        let allowed = self
+            .buckets
+            .entry(name)
+            .or_insert_with(|| TokenBucket::new(Limit::for_message(name), now))
+            .take(now);
+
+        match allowed {
+            true => Verdict::Allow,
+            false => self.strike_at(now),
+        }
+    }
+
+    ///
+    /// Records a strike against the client for something other than going over a limit
+    /// (such as sending a message which can't be parsed).
+    ///
+    pub fn strike(&mut self) -> Verdict {
+        self.strike_at(Instant::now())
+    }
+
+    fn strike_at(&mut self, now: Instant) -> Verdict {
+        match self.strikes.take(now) {
+            true => Verdict::Drop,
+            false => Verdict::Disconnect,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn buckets_allow_a_burst_then_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(Limit::new(3.0, 2.0), start);
+
+        assert!((0..3).all(|_| bucket.take(start)));
+        assert!(!bucket.take(start));
+
+        // half a second at two per second buys exactly one more
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.take(later));
+        assert!(!bucket.take(later));
+
+        // refilling never goes over the burst
+        let much_later = later + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.take(much_later)));
+        assert!(!bucket.take(much_later));
+    }
+
+    #[test]
+    fn messages_over_the_limit_are_dropped() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new_at(now);
+        let burst = Limit::for_message("Chat").burst as usize;
+
+        for _ in 0..burst {
+            assert_eq!(limiter.check_at("Chat", now), Verdict::Allow);
+        }
+        assert_eq!(limiter.check_at("Chat", now), Verdict::Drop);
+
+        // each type of message has its own allowance
+        assert_eq!(limiter.check_at("Turn", now), Verdict::Allow);
+    }
+
+    #[test]
+    fn too_many_strikes_disconnect() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new_at(now);
+        let burst = Limit::for_message("Chat").burst as usize;
+        for _ in 0..burst {
+            limiter.check_at("Chat", now);
+        }
+
+        for _ in 0..MAX_STRIKES as usize {
+            assert_eq!(limiter.check_at("Chat", now), Verdict::Drop);
+        }
+        assert_eq!(limiter.check_at("Chat", now), Verdict::Disconnect);
+    }
+
+    #[test]
+    fn strikes_wear_off() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new_at(start);
+        for _ in 0..MAX_STRIKES as usize {
+            assert_eq!(limiter.strike_at(start), Verdict::Drop);
+        }
+        assert_eq!(limiter.strike_at(start), Verdict::Disconnect);
+
+        let later = start + Duration::from_secs_f64(2.0 / STRIKES_FORGIVEN_PER_SECOND);
+        assert_eq!(limiter.strike_at(later), Verdict::Drop);
+        assert_eq!(limiter.strike_at(later), Verdict::Drop);
+        assert_eq!(limiter.strike_at(later), Verdict::Disconnect);
+    }
+}
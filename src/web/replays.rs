@@ -0,0 +1,36 @@
+use log::error;
+use std::path::PathBuf;
+use tokio::task;
+use warp::{Filter, Rejection, Reply};
+
+use crate::get_error_chain;
+use crate::server::list_replays;
+use crate::web::errors::InternalServerError;
+
+pub fn replays(
+    replay_directory: Option<PathBuf>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let replay_directory = warp::any().map(move || replay_directory.clone());
+
+    warp::path!("api" / "replays")
+        .and(warp::get())
+        .and(replay_directory)
+        .and_then(serve_replay_list)
+}
+
+async fn serve_replay_list(replay_directory: Option<PathBuf>) -> Result<impl Reply, Rejection> {
+    let replay_directory = replay_directory.ok_or_else(warp::reject::not_found)?;
+
+    let replays = task::spawn_blocking(move || list_replays(&replay_directory))
+        .await
+        .map_err(|error| {
+            error!("Failed to list replays: {}", error);
+            warp::reject::custom(InternalServerError)
+        })?
+        .map_err(|error| {
+            error!("Failed to list replays: {}", get_error_chain(error));
+            warp::reject::custom(InternalServerError)
+        })?;
+
+    Ok(warp::reply::json(&replays))
+}
@@ -0,0 +1,280 @@
+use anyhow::{Context, Error, anyhow};
+use futures::future;
+use hyper::server::conn::Http;
+use hyper::service::{Service, service_fn};
+use log::{debug, error, info, warn};
+use std::convert::Infallible;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::{select, time};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use warp::{Filter, Rejection, Reply};
+
+use crate::get_error_chain;
+use crate::signals::{UnixSignal, UnixSignalKind};
+
+/// How often the certificate files are checked for changes.
+const TLS_RELOAD_CHECK_SECONDS: u64 = 10;
+/// How long to wait before accepting again after a failure (such as running out of file
+/// descriptors), doubling with each failure in a row up to the maximum.
+const ACCEPT_RETRY_MIN_MILLISECONDS: u64 = 10;
+const ACCEPT_RETRY_MAX_MILLISECONDS: u64 = 1000;
+
+///
+/// The address of the client on the other end of a TLS connection.
+///
+/// Connections accepted by warp carry their remote address, but TLS connections
+/// are accepted here instead, so their address is passed along as a request extension.
+///
+#[derive(Debug, Clone, Copy)]
+struct RemoteAddr(SocketAddr);
+
+///
+/// Extracts the address of the client which made the request, over plain HTTP or TLS.
+///
+pub fn remote() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Copy {
+    warp::addr::remote()
+        .and(warp::ext::optional::<RemoteAddr>())
+        .map(|plain: Option<SocketAddr>, tls: Option<RemoteAddr>| {
+            tls.map(|RemoteAddr(remote_addr)| remote_addr).or(plain)
+        })
+}
+
+///
+/// Serves routes over TLS, reloading the certificate whenever its files change
+/// or the process receives SIGHUP.
+///
+pub async fn serve<F, R>(
+    routes: F,
+    bind_address: SocketAddr,
+    certificate_path: PathBuf,
+    key_path: PathBuf,
+) -> Result<(), Error>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let resolver = Arc::new(CertificateResolver::new(certificate_path, key_path)?);
+    tokio::spawn(reload_certificate(resolver.clone()));
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+    tls_config.cert_resolver = resolver;
+    tls_config.set_protocols(&[b"http/1.1".to_vec()]);
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+
+    let mut listener = TcpListener::bind(bind_address)
+        .await
+        .with_context(|| anyhow!("Failed to bind to {}", bind_address))?;
+    info!("Listening on https://{}", bind_address);
+
+    let service = warp::service(routes);
+    let mut accept_retry = Duration::from_millis(ACCEPT_RETRY_MIN_MILLISECONDS);
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                warn!(
+                    "Failed to accept connection, retrying in {:?}: {}",
+                    accept_retry, error
+                );
+                time::delay_for(accept_retry).await;
+                accept_retry =
+                    (accept_retry * 2).min(Duration::from_millis(ACCEPT_RETRY_MAX_MILLISECONDS));
+                continue;
+            }
+        };
+        accept_retry = Duration::from_millis(ACCEPT_RETRY_MIN_MILLISECONDS);
+
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    debug!("TLS handshake with {} failed: {}", remote_addr, error);
+                    return;
+                }
+            };
+
+            let service = service_fn(move |mut request| {
+                request.extensions_mut().insert(RemoteAddr(remote_addr));
+                service.clone().call(request)
+            });
+
+            if let Err(error) = Http::new()
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+            {
+                debug!(
+                    "Error occurred in connection with {}: {}",
+                    remote_addr, error
+                );
+            }
+        });
+    }
+}
+
+///
+/// Redirects every request to the same host and path over HTTPS.
+///
+pub fn redirect_to_https(
+    https_port: u16,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("host")
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(
+            move |host: Option<String>, path: warp::path::FullPath, query: String| {
+                let host = host
+                    .and_then(|host| host.parse::<hyper::http::uri::Authority>().ok())
+                    .map(|authority| authority.host().to_owned())
+                    .unwrap_or_else(|| "localhost".to_owned());
+                let port = match https_port {
+                    443 => String::new(),
+                    port => format!(":{}", port),
+                };
+                let query = match query.is_empty() {
+                    true => query,
+                    false => format!("?{}", query),
+                };
+
+                future::ready(
+                    format!("https://{}{}{}{}", host, port, path.as_str(), query)
+                        .parse::<hyper::Uri>()
+                        .map(warp::redirect)
+                        .map_err(|_| warp::reject::not_found()),
+                )
+            },
+        )
+}
+
+///
+/// Hands out the current certificate to each TLS handshake,
+/// and swaps in a new one when asked to reload.
+///
+struct CertificateResolver {
+    certificate_path: PathBuf,
+    key_path: PathBuf,
+    certified_key: RwLock<CertifiedKey>,
+    loaded_modified: Mutex<Option<SystemTime>>,
+}
+
+impl CertificateResolver {
+    fn new(certificate_path: PathBuf, key_path: PathBuf) -> Result<Self, Error> {
+        let loaded_modified = last_modified(&[&certificate_path, &key_path]);
+        let certified_key = load_certified_key(&certificate_path, &key_path)?;
+
+        Ok(Self {
+            certificate_path,
+            key_path,
+            certified_key: RwLock::new(certified_key),
+            loaded_modified: Mutex::new(loaded_modified),
+        })
+    }
+
+    fn has_changed(&self) -> bool {
+        let modified = last_modified(&[&self.certificate_path, &self.key_path]);
+        match self.loaded_modified.lock() {
+            Ok(loaded_modified) => modified.is_some() && modified != *loaded_modified,
+            Err(_) => false,
+        }
+    }
+
+    fn reload(&self) -> Result<(), Error> {
+        let modified = last_modified(&[&self.certificate_path, &self.key_path]);
+        let certified_key = load_certified_key(&self.certificate_path, &self.key_path)?;
+
+        *self
+            .certified_key
+            .write()
+            .map_err(|_| anyhow!("Certificate lock poisoned"))? = certified_key;
+        *self
+            .loaded_modified
+            .lock()
+            .map_err(|_| anyhow!("Certificate lock poisoned"))? = modified;
+
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.certified_key
+            .read()
+            .ok()
+            .map(|certified_key| certified_key.clone())
+    }
+}
+
+async fn reload_certificate(resolver: Arc<CertificateResolver>) {
+    let mut hangups = UnixSignal::new(UnixSignalKind::Hangup);
+    let mut interval = time::interval(Duration::from_secs(TLS_RELOAD_CHECK_SECONDS));
+
+    loop {
+        select! {
+            _ = interval.tick() => {
+                if !resolver.has_changed() {
+                    continue;
+                }
+            }
+            _ = hangups.recv() => {}
+        }
+
+        match resolver.reload() {
+            Ok(()) => info!("Reloaded TLS certificate"),
+            Err(error) => error!(
+                "Failed to reload TLS certificate, still using the previous one: {}",
+                get_error_chain(error)
+            ),
+        }
+    }
+}
+
+fn load_certified_key(certificate_path: &Path, key_path: &Path) -> Result<CertifiedKey, Error> {
+    let certificates = File::open(certificate_path)
+        .map_err(Error::from)
+        .and_then(|file| {
+            pemfile::certs(&mut BufReader::new(file)).map_err(|_| anyhow!("Invalid PEM"))
+        })
+        .with_context(|| anyhow!("Failed to read TLS certificate {:?}", certificate_path))?;
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificates found in {:?}", certificate_path));
+    }
+
+    let read_keys = |parse: fn(&mut dyn std::io::BufRead) -> Result<_, ()>| {
+        File::open(key_path)
+            .map_err(Error::from)
+            .and_then(|file| parse(&mut BufReader::new(file)).map_err(|_| anyhow!("Invalid PEM")))
+            .with_context(|| anyhow!("Failed to read TLS key {:?}", key_path))
+    };
+    let key = read_keys(pemfile::pkcs8_private_keys)?
+        .into_iter()
+        .chain(read_keys(pemfile::rsa_private_keys)?)
+        .next()
+        .ok_or_else(|| anyhow!("No private keys found in {:?}", key_path))?;
+
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|_| anyhow!("Unsupported private key type in {:?}", key_path))?;
+
+    Ok(CertifiedKey::new(certificates, Arc::new(signing_key)))
+}
+
+fn last_modified(paths: &[&Path]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .max()
+}
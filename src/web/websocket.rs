@@ -1,9 +1,10 @@
 use futures::sink::{Sink, SinkExt};
 use futures::stream::{Stream, StreamExt};
 use log::{debug, error, trace, warn};
-use std::net::SocketAddr;
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::select;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
@@ -12,23 +13,87 @@ use tokio::time;
 use warp::reply::Reply;
 use warp::ws::{Message, WebSocket, Ws};
 
-use crate::server::{ClientId, MessageIn, MessageOut};
+use crate::metrics::Metrics;
+use crate::server::{CLOSE_CODE_POLICY_VIOLATION, ClientId, MessageIn, MessageOut};
+use crate::web::connections::ConnectionPermit;
+use crate::web::rate_limit::{RateLimiter, Verdict};
 
-const PING_RATE_SECONDS: u64 = 15;
+/// Close frames can only carry this many bytes of reason.
+const CLOSE_REASON_MAX_BYTES: usize = 123;
+/// Connections which haven't answered a close frame for this long are dropped.
+const CLOSE_TIMEOUT_SECONDS: u64 = 5;
+/// Clients which send a larger message than this are disconnected.
+/// (The largest messages clients send, joining with a name and colour, are far smaller.)
+const MESSAGE_MAX_BYTES: usize = 16 * 1024;
+const RATE_LIMIT_CLOSE_REASON: &str = "Too many messages";
+const PING_RATE_SECONDS: u64 = 5;
+/// Connections which haven't answered a ping for this long are dropped.
+const PING_TIMEOUT_SECONDS: u64 = 20;
+
+///
+/// Pings carry the time they were sent (in milliseconds since the connection was opened),
+/// which clients echo back in their pongs, so the round trip time can be measured.
+///
+#[derive(Debug)]
+struct PingTracker {
+    opened_at: Instant,
+    last_pong_at: AtomicU64,
+}
+
+impl PingTracker {
+    fn new() -> Self {
+        Self {
+            opened_at: Instant::now(),
+            last_pong_at: AtomicU64::new(0),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.opened_at.elapsed().as_millis() as u64
+    }
+
+    fn ping_payload(&self) -> Vec<u8> {
+        self.now().to_be_bytes().to_vec()
+    }
+
+    /// Records a pong, returning the round trip time if the pong echoed one of our pings.
+    fn record_pong(&self, payload: &[u8]) -> Option<u64> {
+        let now = self.now();
+        self.last_pong_at.store(now, Ordering::Relaxed);
+
+        let sent_at = u64::from_be_bytes(<[u8; 8]>::try_from(payload).ok()?);
+        Some(now.saturating_sub(sent_at))
+    }
+
+    fn silent_for(&self) -> Duration {
+        Duration::from_millis(
+            self.now()
+                .saturating_sub(self.last_pong_at.load(Ordering::Relaxed)),
+        )
+    }
+}
 
 pub fn websocket(
     ws: Ws,
-    ip_address: Option<SocketAddr>,
+    ip_address: Option<IpAddr>,
+    permit: Option<ConnectionPermit>,
     server_tx: Sender<MessageIn>,
+    metrics: Arc<Metrics>,
 ) -> impl Reply {
-    let ip_address = ip_address.map(|ip_address| format!("{}", ip_address));
-    ws.on_upgrade(|websocket| handle_websocket(websocket, ip_address, server_tx))
+    ws.max_message_size(MESSAGE_MAX_BYTES)
+        .max_frame_size(MESSAGE_MAX_BYTES)
+        .on_upgrade(move |websocket| {
+            handle_websocket(websocket, ip_address, permit, server_tx, metrics)
+        })
 }
 
 async fn handle_websocket(
     websocket: WebSocket,
-    ip_address: Option<String>,
+    ip_address: Option<IpAddr>,
+    // held until the connection closes
+    _permit: Option<ConnectionPermit>,
     mut server_tx: Sender<MessageIn>,
+    metrics: Arc<Metrics>,
 ) {
     let id = ClientId::default();
 
@@ -45,16 +110,13 @@ async fn handle_websocket(
         return;
     }
 
-    let in_task = tokio::spawn(handle_in(id, ws_rx, server_tx.clone()));
-    let out_task = tokio::spawn(handle_out(messages_rx, ws_tx.clone()));
-    let ping_task = tokio::spawn(handle_ping(ws_tx));
+    let pings = Arc::new(PingTracker::new());
 
-    if let Err(error) = select! {
-        out = in_task => out,
-        out = out_task => out,
-        out = ping_task => out,
-    } {
-        error!("Failure occurred while handling websocket: {}", error);
+    // when any of the handlers finishes, the others are dropped along with the connection
+    select! {
+        _ = handle_in(id, ws_rx, ws_tx.clone(), server_tx.clone(), pings.clone(), metrics.clone()) => {},
+        _ = handle_out(messages_rx, ws_tx.clone(), metrics) => {},
+        _ = handle_ping(ws_tx, pings) => {},
     }
 
     // send client disconnect
@@ -66,9 +128,13 @@ async fn handle_websocket(
 async fn handle_in(
     id: ClientId,
     mut rx: impl Stream<Item = Result<Message, warp::Error>> + Unpin,
+    ws_tx: Arc<Mutex<impl Sink<Message> + Unpin>>,
     mut tx: Sender<MessageIn>,
+    pings: Arc<PingTracker>,
+    metrics: Arc<Metrics>,
 ) {
     debug!("Websocket handler (in) created");
+    let mut rate_limiter = RateLimiter::new();
     while let Some(message) = rx.next().await {
         let message = match message {
             Ok(message) => message,
@@ -83,6 +149,15 @@ async fn handle_in(
             break;
         }
 
+        if message.is_pong() {
+            if let Some(latency) = pings.record_pong(message.as_bytes()) {
+                tx.send(MessageIn::latency(id, latency))
+                    .await
+                    .unwrap_or_else(|error| error!("Failed to send latency to server: {}", error))
+            }
+            continue;
+        }
+
         let text = match message.to_str() {
             Ok(text) => text,
             Err(()) => {
@@ -96,10 +171,42 @@ async fn handle_in(
             Ok(message) => message,
             Err(error) => {
                 warn!("Failed to parse incoming message ({}): {}", text, error);
-                continue;
+                metrics.parse_failures.inc();
+                match rate_limiter.strike() {
+                    Verdict::Disconnect => {
+                        disconnect_rate_limited(id, &ws_tx, &metrics).await;
+                        break;
+                    }
+                    Verdict::Allow | Verdict::Drop => continue,
+                }
             }
         };
 
+        match rate_limiter.check(message.payload.name()) {
+            Verdict::Allow => {}
+            Verdict::Drop => {
+                trace!("Dropping rate limited message: {}", text);
+                metrics
+                    .rate_limited
+                    .with_label_values(&[message.payload.name()])
+                    .inc();
+                continue;
+            }
+            Verdict::Disconnect => {
+                metrics
+                    .rate_limited
+                    .with_label_values(&[message.payload.name()])
+                    .inc();
+                disconnect_rate_limited(id, &ws_tx, &metrics).await;
+                break;
+            }
+        }
+
+        metrics
+            .messages_in
+            .with_label_values(&[message.payload.name()])
+            .inc();
+
         tx.send(message)
             .await
             .unwrap_or_else(|error| error!("Failed to send incoming message to server: {}", error))
@@ -107,9 +214,64 @@ async fn handle_in(
     debug!("Websocket handler (in) closed");
 }
 
-async fn handle_out(mut rx: Receiver<MessageOut>, tx: Arc<Mutex<impl Sink<Message> + Unpin>>) {
+///
+/// Closes the connection of a client which has gone over its rate limits too often.
+///
+async fn disconnect_rate_limited(
+    id: ClientId,
+    tx: &Mutex<impl Sink<Message> + Unpin>,
+    metrics: &Metrics,
+) {
+    warn!("Disconnecting client {} for sending too many messages", id);
+    metrics.rate_limit_disconnects.inc();
+
+    if tx
+        .lock()
+        .await
+        .send(Message::close_with(
+            CLOSE_CODE_POLICY_VIOLATION,
+            RATE_LIMIT_CLOSE_REASON,
+        ))
+        .await
+        .is_err()
+    {
+        error!("Failed to send close");
+    }
+}
+
+async fn handle_out(
+    mut rx: Receiver<MessageOut>,
+    tx: Arc<Mutex<impl Sink<Message> + Unpin>>,
+    metrics: Arc<Metrics>,
+) {
     debug!("Websocket handler (out) created");
     while let Some(message) = rx.recv().await {
+        if let MessageOut::Close { code, mut reason } = message {
+            if reason.len() > CLOSE_REASON_MAX_BYTES {
+                let mut end = CLOSE_REASON_MAX_BYTES;
+                while !reason.is_char_boundary(end) {
+                    end -= 1;
+                }
+                reason.truncate(end);
+            }
+
+            if tx
+                .lock()
+                .await
+                .send(Message::close_with(code, reason))
+                .await
+                .is_err()
+            {
+                error!("Failed to send close");
+                break;
+            }
+
+            // give the client a moment to answer, so the close handshake can finish
+            // (the incoming handler stops once the client's close frame arrives)
+            time::delay_for(Duration::from_secs(CLOSE_TIMEOUT_SECONDS)).await;
+            break;
+        }
+
         let text = match message.to_json() {
             Ok(text) => text,
             Err(error) => {
@@ -121,22 +283,41 @@ async fn handle_out(mut rx: Receiver<MessageOut>, tx: Arc<Mutex<impl Sink<Messag
             }
         };
 
+        let text_bytes = text.len() as u64;
         if tx.lock().await.send(Message::text(text)).await.is_err() {
-            error!("Failed to send outgoing message")
+            error!("Failed to send outgoing message");
+            continue;
         }
+        metrics
+            .messages_out
+            .with_label_values(&[message.name()])
+            .inc();
+        metrics
+            .messages_out_bytes
+            .with_label_values(&[message.name()])
+            .inc_by(text_bytes);
     }
     debug!("Websocket handler (out) closed");
 }
 
-async fn handle_ping(tx: Arc<Mutex<impl Sink<Message> + Unpin>>) {
+async fn handle_ping(tx: Arc<Mutex<impl Sink<Message> + Unpin>>, pings: Arc<PingTracker>) {
     debug!("Websocket handler (ping) created");
     let mut interval = time::interval(Duration::from_secs(PING_RATE_SECONDS));
     loop {
         interval.tick().await;
+
+        if pings.silent_for() > Duration::from_secs(PING_TIMEOUT_SECONDS) {
+            warn!(
+                "Dropping connection which hasn't answered a ping for {} seconds",
+                PING_TIMEOUT_SECONDS
+            );
+            break;
+        }
+
         if tx
             .lock()
             .await
-            .send(Message::ping(Vec::new()))
+            .send(Message::ping(pings.ping_payload()))
             .await
             .is_err()
         {
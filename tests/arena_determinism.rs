@@ -0,0 +1,160 @@
+mod common;
+
+use webtron::server::{Arena, ArenaInput, Direction, Player, PlayerId};
+
+use common::{TICK_SECONDS, next_tick, players, start_arena, started_at};
+
+///
+/// Runs a round with a fixed script of turns, returning the serialized update stream.
+///
+fn run_round(players: &[Player], turns: &[(usize, usize, Direction)]) -> String {
+    let spawns = [
+        (140.0, 140.0, Direction::Right),
+        (420.0, 420.0, Direction::Left),
+        (140.0, 420.0, Direction::Right),
+        (420.0, 140.0, Direction::Left),
+    ];
+    let mut arena = start_arena(players, &spawns);
+
+    let mut now = started_at();
+    for tick in 0..400 {
+        for (_, player_index, direction) in turns.iter().filter(|(at, _, _)| *at == tick) {
+            arena.process_input_at(now, players[*player_index].id, ArenaInput::Turn(*direction));
+        }
+
+        arena.update_at(now, TICK_SECONDS);
+        now = next_tick(now);
+
+        if arena.started.is_none() {
+            break;
+        }
+    }
+
+    serde_json::to_string(&arena.updates).unwrap()
+}
+
+#[test]
+fn same_inputs_produce_identical_update_streams() {
+    let players = players(4);
+    let turns = [
+        (10, 0, Direction::Up),
+        (10, 1, Direction::Down),
+        (25, 2, Direction::Down),
+        (30, 3, Direction::Up),
+        (40, 0, Direction::Right),
+        (55, 1, Direction::Left),
+    ];
+
+    let first = run_round(&players, &turns);
+    for _ in 0..10 {
+        assert_eq!(first, run_round(&players, &turns));
+    }
+}
+
+#[test]
+fn rounds_started_by_input_produce_identical_update_streams() {
+    let players = players(4);
+    let turns = [
+        (70, 0, Direction::Left),
+        (70, 1, Direction::Right),
+        (90, 2, Direction::Up),
+        (95, 3, Direction::Down),
+    ];
+
+    let run_round = || {
+        let mut arena = Arena::with_name("start");
+        for player in &players {
+            arena.add_player(player.clone());
+        }
+        arena.apply_updates();
+
+        // the countdown runs in arena time, so the round plays out the same way every time
+        let mut now = started_at();
+        arena.process_input_at(now, players[0].id, ArenaInput::Start);
+        for tick in 0..1000 {
+            for (_, player_index, direction) in turns.iter().filter(|(at, _, _)| *at == tick) {
+                arena.process_input_at(
+                    now,
+                    players[*player_index].id,
+                    ArenaInput::Turn(*direction),
+                );
+            }
+
+            arena.update_at(now, TICK_SECONDS);
+            now = next_tick(now);
+
+            if arena.winner.is_some() && arena.started.is_none() {
+                break;
+            }
+        }
+
+        serde_json::to_string(&arena.updates).unwrap()
+    };
+
+    let first = run_round();
+    assert!(first.contains("AddLightcycle") && first.contains("\"End\""));
+    for _ in 0..10 {
+        assert_eq!(first, run_round());
+    }
+}
+
+#[test]
+fn simultaneous_collisions_resolve_the_same_way() {
+    let players = players(2);
+
+    // head on, meeting in the middle on the same tick
+    let spawns = [
+        (200.0, 280.0, Direction::Right),
+        (360.0, 280.0, Direction::Left),
+    ];
+
+    let outcome = || {
+        let mut arena = start_arena(&players, &spawns);
+        let mut now = started_at();
+        while arena.started.is_some() {
+            arena.update_at(now, TICK_SECONDS);
+            now = next_tick(now);
+        }
+
+        (
+            arena.winner,
+            serde_json::to_string(&arena.lightcycles).unwrap(),
+            serde_json::to_string(&arena.updates).unwrap(),
+        )
+    };
+
+    let first = outcome();
+    for _ in 0..10 {
+        assert_eq!(first, outcome());
+    }
+}
+
+#[test]
+fn entities_keep_join_order() {
+    let players = players(4);
+    let mut arena = Arena::with_name("order");
+    for player in &players {
+        arena.add_player(player.clone());
+    }
+    arena.apply_updates();
+
+    let joined: Vec<PlayerId> = players.iter().map(|player| player.id).collect();
+    assert_eq!(arena.players.keys().copied().collect::<Vec<_>>(), joined);
+
+    // removing a player leaves everyone else in place
+    arena.remove_player(joined[1]);
+    arena.apply_updates();
+    let remaining = vec![joined[0], joined[2], joined[3]];
+    assert_eq!(arena.players.keys().copied().collect::<Vec<_>>(), remaining);
+
+    // the order survives a round trip through the json sent to clients
+    let json = serde_json::to_string(&arena).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert!(value["players"].is_object());
+
+    let deserialized: Arena = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        deserialized.players.keys().copied().collect::<Vec<_>>(),
+        remaining
+    );
+}
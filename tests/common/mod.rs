@@ -0,0 +1,63 @@
+#![allow(dead_code)]
+
+use chrono::{DateTime, Duration as OldDuration, Utc};
+use webtron::server::{
+    Arena, ArenaPoint, ArenaUpdate, Direction, Lightcycle, Lightribbon, Player, PlayerColor,
+};
+
+pub const TICK_SECONDS: f64 = 0.05;
+
+pub fn players(count: usize) -> Vec<Player> {
+    PlayerColor::NAMED
+        .iter()
+        .take(count)
+        .enumerate()
+        .map(|(index, color)| Player {
+            name: format!("P{}", index + 1),
+            color: *color,
+            ..Default::default()
+        })
+        .collect()
+}
+
+pub fn started_at() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_600_000_000, 0).unwrap()
+}
+
+pub fn next_tick(now: DateTime<Utc>) -> DateTime<Utc> {
+    now + OldDuration::milliseconds(50)
+}
+
+///
+/// Builds a fresh arena for the given players, spawning them at the given points
+/// rather than through ArenaInput::Start (which uses the arena's own spawnpoints),
+/// and skipping the countdown.
+///
+pub fn start_arena(players: &[Player], spawns: &[(f64, f64, Direction)]) -> Arena {
+    let mut arena = Arena::with_name("test");
+
+    for player in players {
+        arena.add_player(player.clone());
+    }
+    for (player, (x, y, direction)) in players.iter().zip(spawns) {
+        let position = ArenaPoint::new(*x, *y);
+        arena.updates.push(ArenaUpdate::AddLightcycle(
+            player.id,
+            Lightcycle {
+                position,
+                direction: *direction,
+                ..Default::default()
+            },
+        ));
+        arena.updates.push(ArenaUpdate::AddLightribbon(
+            player.id,
+            Lightribbon {
+                points: vec![position, position],
+            },
+        ));
+    }
+    arena.updates.push(ArenaUpdate::Start(started_at()));
+    arena.apply_updates();
+
+    arena
+}
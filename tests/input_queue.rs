@@ -0,0 +1,179 @@
+mod common;
+
+use webtron::server::{
+    Arena, ArenaInput, ArenaPoint, Direction, InputOutcome, PlayerId, ProcessedInput,
+};
+
+use common::{TICK_SECONDS, next_tick, players, start_arena, started_at};
+
+fn start_single_player_arena() -> (Arena, PlayerId) {
+    let players = players(1);
+    let arena = start_arena(&players, &[(280.0, 280.0, Direction::Right)]);
+
+    (arena, players[0].id)
+}
+
+fn turn_then_tick(arena: &mut Arena, player_id: PlayerId, directions: &[Direction]) {
+    for direction in directions {
+        arena.process_input_at(started_at(), player_id, ArenaInput::Turn(*direction));
+    }
+    arena.update_at(started_at(), TICK_SECONDS);
+}
+
+fn sequenced_turn(arena: &mut Arena, player_id: PlayerId, direction: Direction, sequence: u32) {
+    arena.process_sequenced_input_at(
+        started_at(),
+        player_id,
+        ArenaInput::Turn(direction),
+        Some(sequence),
+    );
+}
+
+fn outcomes(arena: &mut Arena) -> Vec<(u32, InputOutcome)> {
+    arena
+        .take_processed_inputs()
+        .iter()
+        .map(
+            |ProcessedInput {
+                 sequence, outcome, ..
+             }| (*sequence, *outcome),
+        )
+        .collect()
+}
+
+fn ribbon_points(arena: &Arena, player_id: PlayerId) -> Vec<ArenaPoint> {
+    arena.lightribbons[&player_id].points.clone()
+}
+
+#[test]
+fn turns_within_one_tick_are_spread_over_the_following_ticks() {
+    let (mut arena, player_id) = start_single_player_arena();
+    arena.update_at(started_at(), TICK_SECONDS);
+
+    turn_then_tick(&mut arena, player_id, &[Direction::Up, Direction::Left]);
+    assert_eq!(arena.lightcycles[&player_id].direction, Direction::Up);
+
+    arena.update_at(started_at(), TICK_SECONDS);
+    assert_eq!(arena.lightcycles[&player_id].direction, Direction::Left);
+
+    // each turn got a segment of its own, so there are no zero length segments
+    let points = ribbon_points(&arena, player_id);
+    assert_eq!(points.len(), 4);
+    assert!(points.windows(2).all(|line| line[0] != line[1]));
+}
+
+#[test]
+fn quick_reversal_is_made_as_two_turns() {
+    let (mut arena, player_id) = start_single_player_arena();
+
+    // right -> up -> left -> down, all pressed within a single tick
+    turn_then_tick(
+        &mut arena,
+        player_id,
+        &[Direction::Up, Direction::Left, Direction::Down],
+    );
+    arena.update_at(started_at(), TICK_SECONDS);
+    arena.update_at(started_at(), TICK_SECONDS);
+
+    let lightcycle = arena.lightcycles[&player_id];
+    assert_eq!(lightcycle.direction, Direction::Down);
+    assert!(!lightcycle.dead);
+    assert_eq!(ribbon_points(&arena, player_id).len(), 5);
+}
+
+#[test]
+fn opposite_turns_are_still_refused() {
+    let (mut arena, player_id) = start_single_player_arena();
+
+    turn_then_tick(&mut arena, player_id, &[Direction::Left]);
+    assert_eq!(arena.lightcycles[&player_id].direction, Direction::Right);
+    assert_eq!(ribbon_points(&arena, player_id).len(), 2);
+}
+
+#[test]
+fn turns_beyond_the_max_queue_depth_are_dropped() {
+    let (mut arena, player_id) = start_single_player_arena();
+    arena.set_max_queued_turns(2);
+
+    turn_then_tick(
+        &mut arena,
+        player_id,
+        &[
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ],
+    );
+    for _ in 0..5 {
+        arena.update_at(started_at(), TICK_SECONDS);
+    }
+
+    // only up and left were queued
+    assert_eq!(arena.lightcycles[&player_id].direction, Direction::Left);
+    assert_eq!(ribbon_points(&arena, player_id).len(), 4);
+}
+
+#[test]
+fn turns_made_before_the_round_starts_are_dropped() {
+    let (mut arena, player_id) = start_single_player_arena();
+
+    let before_start = started_at() - chrono::Duration::milliseconds(500);
+    arena.process_input_at(before_start, player_id, ArenaInput::Turn(Direction::Up));
+    arena.update_at(before_start, TICK_SECONDS);
+    arena.update_at(started_at(), TICK_SECONDS);
+    arena.update_at(next_tick(started_at()), TICK_SECONDS);
+
+    assert_eq!(arena.lightcycles[&player_id].direction, Direction::Right);
+}
+
+#[test]
+fn sequenced_turns_are_only_acknowledged_once_made() {
+    let (mut arena, player_id) = start_single_player_arena();
+
+    sequenced_turn(&mut arena, player_id, Direction::Up, 1);
+    sequenced_turn(&mut arena, player_id, Direction::Left, 2);
+    assert_eq!(outcomes(&mut arena), vec![]);
+
+    arena.update_at(started_at(), TICK_SECONDS);
+    assert_eq!(
+        outcomes(&mut arena),
+        vec![(1, InputOutcome::Applied(arena.tick))]
+    );
+
+    arena.update_at(started_at(), TICK_SECONDS);
+    assert_eq!(
+        outcomes(&mut arena),
+        vec![(2, InputOutcome::Applied(arena.tick))]
+    );
+}
+
+#[test]
+fn sequenced_turns_which_are_dropped_or_refused_are_reported() {
+    let (mut arena, player_id) = start_single_player_arena();
+    arena.set_max_queued_turns(1);
+
+    // the queue is full for the second turn, and the third is a reversal once the first is made
+    sequenced_turn(&mut arena, player_id, Direction::Left, 1);
+    sequenced_turn(&mut arena, player_id, Direction::Up, 2);
+    assert_eq!(outcomes(&mut arena), vec![(2, InputOutcome::Dropped)]);
+
+    arena.update_at(started_at(), TICK_SECONDS);
+    assert_eq!(outcomes(&mut arena), vec![(1, InputOutcome::Dropped)]);
+}
+
+#[test]
+fn sequenced_turns_made_before_the_round_starts_are_reported_as_dropped() {
+    let (mut arena, player_id) = start_single_player_arena();
+
+    let before_start = started_at() - chrono::Duration::milliseconds(500);
+    arena.process_sequenced_input_at(
+        before_start,
+        player_id,
+        ArenaInput::Turn(Direction::Up),
+        Some(7),
+    );
+    arena.update_at(before_start, TICK_SECONDS);
+
+    assert_eq!(outcomes(&mut arena), vec![(7, InputOutcome::Dropped)]);
+}
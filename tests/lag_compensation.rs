@@ -0,0 +1,85 @@
+mod common;
+
+use webtron::server::{ARENA_LAG_COMPENSATION_MAX_TICKS, Arena, ArenaInput, Direction, PlayerId};
+
+use common::{TICK_SECONDS, players, start_arena, started_at};
+
+const SPAWN_X: f64 = 280.0;
+const LIGHTCYCLE_SPEED: f64 = 55.0;
+
+///
+/// Starts a single player arena with the given ping, and runs it for ten ticks.
+///
+fn start_arena_with_ping(ping_milliseconds: Option<u64>) -> (Arena, PlayerId) {
+    let players = players(1);
+    let player_id = players[0].id;
+    let mut arena = start_arena(&players, &[(SPAWN_X, 280.0, Direction::Right)]);
+
+    if let Some(ping_milliseconds) = ping_milliseconds {
+        arena.set_player_ping(player_id, ping_milliseconds);
+    }
+    for _ in 0..10 {
+        arena.update_at(started_at(), TICK_SECONDS);
+    }
+
+    (arena, player_id)
+}
+
+/// Turns up as though the client was showing `client_tick`, returning where the turn was placed.
+fn turn_at(arena: &mut Arena, player_id: PlayerId, client_tick: u64) -> f64 {
+    arena.process_input_at(
+        started_at(),
+        player_id,
+        ArenaInput::TurnAt(Direction::Up, client_tick),
+    );
+    arena.update_at(started_at(), TICK_SECONDS);
+
+    let points = &arena.lightribbons[&player_id].points;
+    points[points.len() - 2].x
+}
+
+/// Where the lightcycle was after the given tick.
+fn x_after_tick(tick: u64) -> f64 {
+    SPAWN_X + LIGHTCYCLE_SPEED * TICK_SECONDS * tick as f64
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "expected {} but got {}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn turns_are_not_rewound_without_a_measured_ping() {
+    let (mut arena, player_id) = start_arena_with_ping(None);
+
+    assert_close(turn_at(&mut arena, player_id, 0), x_after_tick(10));
+}
+
+#[test]
+fn turns_are_rewound_no_further_than_the_measured_ping() {
+    // 100 ms is two ticks, however far back the client claims to have been
+    let (mut arena, player_id) = start_arena_with_ping(Some(100));
+
+    assert_close(turn_at(&mut arena, player_id, 0), x_after_tick(8));
+}
+
+#[test]
+fn turns_are_rewound_to_the_client_tick_within_the_measured_ping() {
+    let (mut arena, player_id) = start_arena_with_ping(Some(300));
+
+    assert_close(turn_at(&mut arena, player_id, 9), x_after_tick(9));
+}
+
+#[test]
+fn turns_are_rewound_no_further_than_the_maximum() {
+    let (mut arena, player_id) = start_arena_with_ping(Some(10_000));
+
+    assert_close(
+        turn_at(&mut arena, player_id, 0),
+        x_after_tick(10 - ARENA_LAG_COMPENSATION_MAX_TICKS),
+    );
+}
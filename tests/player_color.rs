@@ -0,0 +1,31 @@
+use webtron::server::PlayerColor;
+
+#[test]
+fn named_and_hex_colors_are_parsed() {
+    assert_eq!("Blue".parse::<PlayerColor>().unwrap(), PlayerColor::Blue);
+    assert_eq!(
+        " #1A2b3C ".parse::<PlayerColor>().unwrap(),
+        PlayerColor::Rgb(0x1a, 0x2b, 0x3c)
+    );
+}
+
+#[test]
+fn non_ascii_colors_are_rejected() {
+    // six bytes, but "é" is two of them
+    assert!("#aé12a".parse::<PlayerColor>().is_err());
+    assert!("#ééé".parse::<PlayerColor>().is_err());
+}
+
+#[test]
+fn colors_of_the_wrong_length_are_rejected() {
+    assert!("#".parse::<PlayerColor>().is_err());
+    assert!("#12345".parse::<PlayerColor>().is_err());
+    assert!("#1234567".parse::<PlayerColor>().is_err());
+    assert!("123456".parse::<PlayerColor>().is_err());
+}
+
+#[test]
+fn colors_with_other_characters_are_rejected() {
+    assert!("#12345g".parse::<PlayerColor>().is_err());
+    assert!("#+12345".parse::<PlayerColor>().is_err());
+}
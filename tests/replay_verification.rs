@@ -0,0 +1,118 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use webtron::config::Config;
+use webtron::server::{ArenaInput, ArenaUpdate, Direction, Replay, ReplayId, ReplayRecorder};
+use webtron::simulation::verify_replay;
+
+use common::{players, start_arena, started_at};
+
+///
+/// Records a round between two players, one of whom makes lag compensated turns,
+/// stepping it as a server with the given config would, and writes it to `directory`.
+///
+fn record_round(directory: &Path, config: &Config) -> PathBuf {
+    let players = players(2);
+    let mut arena = start_arena(
+        &players,
+        &[
+            (140.0, 280.0, Direction::Right),
+            (420.0, 140.0, Direction::Left),
+        ],
+    );
+    let mut recorder = ReplayRecorder::new(config.tick_rate);
+    assert!(recorder.observe(&arena).is_none());
+
+    arena.set_player_ping(players[0].id, 150);
+    for tick in 0..1000 {
+        let turn = match tick {
+            10 => Some((0, ArenaInput::TurnAt(Direction::Up, 8))),
+            20 => Some((1, ArenaInput::Turn(Direction::Down))),
+            30 => Some((0, ArenaInput::TurnAt(Direction::Right, 25))),
+            // refused, as it reverses the lightcycle
+            31 => Some((0, ArenaInput::Turn(Direction::Left))),
+            _ => None,
+        };
+        if let Some((player_index, input)) = turn {
+            arena.process_input_at(started_at(), players[player_index].id, input);
+        }
+
+        arena.update_at(started_at(), config.tick_seconds());
+        if let Some(replay) = recorder.observe(&arena) {
+            return replay.write(directory).unwrap();
+        }
+    }
+
+    panic!("The round never ended");
+}
+
+fn with_replay_directory(test: impl FnOnce(&Path)) {
+    let directory = std::env::temp_dir().join(format!("webtron-test-{}", ReplayId::default()));
+    test(&directory);
+    fs::remove_dir_all(&directory).ok();
+}
+
+#[test]
+fn recorded_rounds_verify() {
+    with_replay_directory(|directory| {
+        let path = record_round(directory, &Config::default());
+        let replay = Replay::read(&path).unwrap();
+
+        assert!(replay.frames.iter().any(|frame| !frame.turns.is_empty()));
+        assert_eq!(verify_replay(&path).unwrap(), replay.frames.len());
+    });
+}
+
+#[test]
+fn rounds_recorded_at_tick_rates_which_dont_divide_a_second_verify() {
+    with_replay_directory(|directory| {
+        for tick_rate in [60, 64, 128] {
+            let config = Config {
+                tick_rate,
+                send_rate: tick_rate,
+                ..Config::default()
+            };
+            let path = record_round(directory, &config);
+            let replay = Replay::read(&path).unwrap();
+
+            assert_eq!(replay.header.tick_seconds, 1.0 / tick_rate as f64);
+            assert_eq!(verify_replay(&path).unwrap(), replay.frames.len());
+        }
+    });
+}
+
+#[test]
+fn recorded_outcomes_which_disagree_with_the_inputs_are_caught() {
+    with_replay_directory(|directory| {
+        let path = record_round(directory, &Config::default());
+        let mut replay = Replay::read(&path).unwrap();
+
+        // claim that the first turn went the other way
+        let update = replay
+            .frames
+            .iter_mut()
+            .flat_map(|frame| frame.updates.iter_mut())
+            .find(|update| matches!(update, ArenaUpdate::UpdateLightcycleDirection(..)))
+            .unwrap();
+        if let ArenaUpdate::UpdateLightcycleDirection(_, direction) = update {
+            *direction = Direction::Down;
+        }
+        replay.write(directory).unwrap();
+
+        assert!(verify_replay(&path).is_err());
+    });
+}
+
+#[test]
+fn recorded_ticks_which_disagree_with_the_simulation_are_caught() {
+    with_replay_directory(|directory| {
+        let path = record_round(directory, &Config::default());
+        let mut replay = Replay::read(&path).unwrap();
+
+        replay.frames[5].tick += 1;
+        replay.write(directory).unwrap();
+
+        assert!(verify_replay(&path).is_err());
+    });
+}
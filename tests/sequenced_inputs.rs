@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver};
+use webtron::config::Config;
+use webtron::metrics::Metrics;
+use webtron::server::{
+    BanList, ClientId, Heartbeat, InputRejectedReason, MessageIn, MessageOut, Server,
+};
+
+fn new_server() -> Server {
+    let config = Arc::new(Config::default());
+    let (tx, rx) = mpsc::channel(64);
+
+    Server::new(
+        rx,
+        tx,
+        config.clone(),
+        Arc::new(Metrics::new().unwrap()),
+        Arc::new(Heartbeat::new(config.tick_duration())),
+        Arc::new(BanList::default()),
+    )
+}
+
+async fn send(server: &mut Server, client_id: ClientId, json: &str) {
+    let message = MessageIn::from_json(client_id, json).unwrap();
+    server
+        .handle_message(message.client_id, message.payload)
+        .await
+        .unwrap();
+}
+
+async fn connect(server: &mut Server) -> (ClientId, Receiver<MessageOut>) {
+    let client_id = ClientId::default();
+    let (tx, rx) = mpsc::channel(256);
+    let message = MessageIn::connect(client_id, None, tx);
+    server
+        .handle_message(message.client_id, message.payload)
+        .await
+        .unwrap();
+
+    (client_id, rx)
+}
+
+async fn tick(server: &mut Server) {
+    server.update(Config::default().tick_seconds());
+    server.send_input_outcomes().await;
+    server.send_updates().await;
+}
+
+/// The acknowledgements and rejections the client has been sent so far.
+fn input_replies(rx: &mut Receiver<MessageOut>) -> Vec<String> {
+    let mut replies = Vec::new();
+    while let Ok(message) = rx.try_recv() {
+        match message {
+            MessageOut::InputAck { sequence, .. } => replies.push(format!("ack {}", sequence)),
+            MessageOut::InputRejected { sequence, reason } => {
+                replies.push(format!("rejected {} {:?}", sequence, reason))
+            }
+            _ => {}
+        }
+    }
+
+    replies
+}
+
+#[tokio::test]
+async fn stale_and_duplicate_sequences_are_rejected_and_not_made() {
+    let mut server = new_server();
+    let (first, mut first_rx) = connect(&mut server).await;
+    let (second, _second_rx) = connect(&mut server).await;
+
+    send(
+        &mut server,
+        first,
+        r#"{"Join":{"player":{"name":"first","color":"blue"},"arena_id":null}}"#,
+    )
+    .await;
+    let arena_id = loop {
+        if let MessageOut::ArenaJoined(arena_id, _, _) = first_rx.recv().await.unwrap() {
+            break arena_id;
+        }
+    };
+    send(
+        &mut server,
+        second,
+        &format!(
+            r#"{{"Join":{{"player":{{"name":"second","color":"green"}},"arena_id":"{}"}}}}"#,
+            arena_id
+        ),
+    )
+    .await;
+
+    send(&mut server, first, r#""Start""#).await;
+    tick(&mut server).await;
+    // wait out the countdown
+    tokio::time::delay_for(Duration::from_millis(1100)).await;
+    tick(&mut server).await;
+    input_replies(&mut first_rx);
+
+    send(
+        &mut server,
+        first,
+        r#"{"Turn":{"direction":"left","sequence":2,"tick":0}}"#,
+    )
+    .await;
+    // a repeat of the same sequence number, then an older one, both of which would be valid turns
+    send(
+        &mut server,
+        first,
+        r#"{"Turn":{"direction":"down","sequence":2,"tick":0}}"#,
+    )
+    .await;
+    send(
+        &mut server,
+        first,
+        r#"{"Turn":{"direction":"up","sequence":1,"tick":0}}"#,
+    )
+    .await;
+    assert_eq!(
+        input_replies(&mut first_rx),
+        vec![
+            format!("rejected 2 {:?}", InputRejectedReason::OutOfOrder),
+            format!("rejected 1 {:?}", InputRejectedReason::OutOfOrder),
+        ]
+    );
+
+    for _ in 0..5 {
+        tick(&mut server).await;
+    }
+    assert_eq!(input_replies(&mut first_rx), vec!["ack 2"]);
+
+    // later sequence numbers are still made
+    send(
+        &mut server,
+        first,
+        r#"{"Turn":{"direction":"down","sequence":3,"tick":0}}"#,
+    )
+    .await;
+    tick(&mut server).await;
+    assert_eq!(input_replies(&mut first_rx), vec!["ack 3"]);
+}